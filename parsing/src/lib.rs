@@ -1,8 +1,118 @@
 use once_cell::sync::Lazy;
 pub use simple_ast::MarkdownNode;
 use simple_ast::{regex::Regex, Parser, Rule, Styled};
+use std::{ops::Range, rc::Rc, sync::RwLock};
 
-pub fn parse_markdown(str: &str) -> Styled<MarkdownNode> {
+/// Discord markdown, with the elements simple_ast has no nodes for
+#[derive(Debug)]
+pub struct Markdown<'a> {
+    pub nodes: Vec<Rc<RwLock<MarkdownNode>>>,
+    /// Elements which are left as placeholder characters in `Text` nodes
+    pub elements: Vec<Element<'a>>,
+}
+
+impl<'a> Markdown<'a> {
+    /// The element a character of a `Text` node is a placeholder for
+    pub fn element(&self, c: char) -> Option<&Element<'a>> {
+        element(&self.elements, c)
+    }
+
+    /// Split the text of a `Text` node into plain text and the elements it has placeholders for
+    pub fn segments<'m, 't>(&'m self, text: &'t str) -> impl Iterator<Item = Segment<'t, 'm>> {
+        let elements: &'m [Element<'m>] = &self.elements;
+        let mut rest = text;
+        let mut next_element = None;
+        std::iter::from_fn(move || {
+            if let Some(element) = next_element.take() {
+                return Some(Segment::Element(element));
+            }
+            if rest.is_empty() {
+                return None;
+            }
+
+            let found = rest
+                .char_indices()
+                .find_map(|(i, c)| element(elements, c).map(|element| (i, c, element)));
+            match found {
+                Some((0, c, element)) => {
+                    rest = &rest[c.len_utf8()..];
+                    Some(Segment::Element(element))
+                },
+                Some((i, c, element)) => {
+                    let text = &rest[..i];
+                    rest = &rest[i + c.len_utf8()..];
+                    next_element = Some(element);
+                    Some(Segment::Text(text))
+                },
+                None => Some(Segment::Text(std::mem::take(&mut rest))),
+            }
+        })
+    }
+}
+
+fn element<'e, 'a>(elements: &'e [Element<'a>], c: char) -> Option<&'e Element<'a>> {
+    let index = (c as u32).checked_sub(PLACEHOLDERS.start)?;
+    elements.get(index as usize)
+}
+
+/// A part of a `Text` node
+#[derive(Debug)]
+pub enum Segment<'t, 'm> {
+    Text(&'t str),
+    Element(&'m Element<'m>),
+}
+
+#[derive(Debug)]
+pub enum Element<'a> {
+    /// The start of a line which is styled as a whole
+    Line(Line<'a>),
+    /// `[text](url)`
+    MaskedLink {
+        text: Vec<Rc<RwLock<MarkdownNode>>>,
+        url: &'a str,
+    },
+    /// `<url>`, which suppresses the embed
+    SuppressedLink(&'a str),
+    /// A character of the message which is in the placeholder range
+    Literal(char),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Line<'a> {
+    Heading(usize),
+    ListItem {
+        /// Leading spaces, used for nesting
+        indent: usize,
+        /// The number of a numbered list item
+        number: Option<&'a str>,
+    },
+    Subtext,
+}
+
+/// Placeholders are taken from a private use plane, which simple_ast leaves in text
+const PLACEHOLDERS: Range<u32> = 0xF0000..0xFFFFE;
+
+/// Parse discord markdown
+///
+/// Elements which simple_ast has no nodes for are replaced with placeholder characters, so the
+/// `Text` nodes contain placeholders rather than the source of those elements. Use
+/// [`Markdown::segments`] to read them.
+pub fn parse_markdown(str: &str) -> Markdown<'_> {
+    let mut elements = Vec::new();
+    let nodes = parse(str, true, &mut elements);
+    Markdown { nodes, elements }
+}
+
+fn parse<'a>(
+    str: &'a str,
+    lines: bool,
+    elements: &mut Vec<Element<'a>>,
+) -> Vec<Rc<RwLock<MarkdownNode>>> {
+    let source = replace_elements(str, lines, elements);
+    parse_nodes(&source).0
+}
+
+fn parse_nodes(str: &str) -> Styled<MarkdownNode> {
     use simple_ast::markdown_rules::*;
     let rules: &[&dyn Rule<MarkdownNode>] = &[
         &Escape,
@@ -26,10 +136,169 @@ pub fn parse_markdown(str: &str) -> Styled<MarkdownNode> {
     Parser::with_rules(rules).parse(str)
 }
 
+/// Replace the elements of a message with placeholders, leaving code spans and blocks untouched
+///
+/// Line elements are only looked for when `lines` is set
+fn replace_elements<'a>(str: &'a str, lines: bool, elements: &mut Vec<Element<'a>>) -> String {
+    let mut out = String::with_capacity(str.len());
+    let mut line_start = lines;
+    let mut i = 0;
+
+    while let Some(c) = str[i..].chars().next() {
+        let rest = &str[i..];
+        if line_start {
+            line_start = false;
+            if let Some((line, len)) = line_element(rest) {
+                push_element(&mut out, elements, Element::Line(line), &rest[..len]);
+                i += len;
+                continue;
+            }
+        }
+
+        let element = match c {
+            '\\' => {
+                let len = 1 + rest[1..].chars().next().map_or(0, char::len_utf8);
+                out.push_str(&rest[..len]);
+                i += len;
+                continue;
+            },
+            '`' => {
+                let len = code_span_len(rest).unwrap_or_else(|| fence_len(rest));
+                out.push_str(&rest[..len]);
+                i += len;
+                continue;
+            },
+            '\n' => {
+                line_start = lines;
+                None
+            },
+            '[' => masked_link(rest).map(|(text, url, len)| {
+                let text = parse(text, false, elements);
+                (Element::MaskedLink { text, url }, len)
+            }),
+            '<' => suppressed_link(rest).map(|(url, len)| (Element::SuppressedLink(url), len)),
+            c if PLACEHOLDERS.contains(&(c as u32)) => Some((Element::Literal(c), c.len_utf8())),
+            _ => None,
+        };
+
+        match element {
+            Some((element, len)) => {
+                push_element(&mut out, elements, element, &rest[..len]);
+                i += len;
+            },
+            None => {
+                out.push(c);
+                i += c.len_utf8();
+            },
+        }
+    }
+
+    out
+}
+
+/// Add the placeholder of an element, or its source once the placeholders run out
+fn push_element<'a>(
+    out: &mut String,
+    elements: &mut Vec<Element<'a>>,
+    element: Element<'a>,
+    source: &str,
+) {
+    match std::char::from_u32(PLACEHOLDERS.start + elements.len() as u32)
+        .filter(|c| PLACEHOLDERS.contains(&(*c as u32)))
+    {
+        Some(placeholder) => {
+            out.push(placeholder);
+            elements.push(element);
+        },
+        None => out.push_str(source),
+    }
+}
+
+/// Parse a heading, list item or subtext marker at the start of a line, returning it and its length
+fn line_element(line: &str) -> Option<(Line<'_>, usize)> {
+    if line.starts_with("-# ") {
+        return Some((Line::Subtext, 3));
+    }
+
+    let level = line.bytes().take_while(|&b| b == b'#').count();
+    if (1..=3).contains(&level) && line[level..].starts_with(' ') {
+        return Some((Line::Heading(level), level + 1));
+    }
+
+    let trimmed = line.trim_start_matches(' ');
+    let indent = line.len() - trimmed.len();
+    if trimmed.starts_with("- ") || trimmed.starts_with("* ") {
+        return Some((
+            Line::ListItem {
+                indent,
+                number: None,
+            },
+            indent + 2,
+        ));
+    }
+    let digits = trimmed.bytes().take_while(u8::is_ascii_digit).count();
+    if digits > 0 && trimmed[digits..].starts_with(". ") {
+        return Some((
+            Line::ListItem {
+                indent,
+                number: Some(&trimmed[..digits]),
+            },
+            indent + digits + 2,
+        ));
+    }
+
+    None
+}
+
+fn is_url(url: &str) -> bool {
+    (url.starts_with("https://") || url.starts_with("http://"))
+        && !url.contains(char::is_whitespace)
+}
+
+/// Parse a masked link at the start of `str`, returning its text, url and length
+fn masked_link(str: &str) -> Option<(&str, &str, usize)> {
+    let text_end = str.find(']')?;
+    let text = &str[1..text_end];
+    let rest = str[text_end + 1..].strip_prefix('(')?;
+    let url_end = rest.find(')')?;
+    let raw_url = &rest[..url_end];
+    let url = raw_url
+        .strip_prefix('<')
+        .and_then(|url| url.strip_suffix('>'))
+        .unwrap_or(raw_url);
+
+    if text.is_empty() || text.contains('\n') || !is_url(url) {
+        return None;
+    }
+    Some((text, url, text_end + 2 + url_end + 1))
+}
+
+/// Parse a suppressed link at the start of `str`, returning its url and length
+fn suppressed_link(str: &str) -> Option<(&str, usize)> {
+    let end = str.find('>')?;
+    let url = &str[1..end];
+    if !is_url(url) {
+        return None;
+    }
+    Some((url, end + 1))
+}
+
+fn fence_len(str: &str) -> usize {
+    str.bytes().take_while(|&b| b == b'`').count()
+}
+
+/// The length of the code span or block at the start of `str`, if it is closed
+fn code_span_len(str: &str) -> Option<usize> {
+    let ticks = fence_len(str);
+    let fence = &str[..ticks];
+    str[ticks..].find(fence).map(|end| ticks + end + ticks)
+}
+
 static LINE_SUB_REGEX: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"^(\d+)?s/(.*?(?<!\\))/(.*?(?<!\\))(?:/|$)(\w+)?").unwrap());
 static REACTION_REGEX: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"^(\d+)?([+\-])(<:.+:(\d+)>|.*).*$").unwrap());
+static REPLY_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^>(\d+) ([\s\S]+)").unwrap());
 
 #[derive(Debug)]
 pub enum LineEdit<'a> {
@@ -103,3 +372,83 @@ impl<'a> Reaction<'a> {
         })
     }
 }
+
+#[derive(Debug)]
+pub struct Reply<'a> {
+    pub line: usize,
+    pub content: &'a str,
+}
+
+impl<'a> Reply<'a> {
+    pub fn parse(input: &'a str) -> Option<Self> {
+        let caps = REPLY_REGEX.captures(input)?;
+        let line = caps.at(1).and_then(|l| l.parse().ok()).filter(|&l| l > 0)?;
+        let content = caps.at(2)?;
+
+        if content.trim().is_empty() {
+            return None;
+        }
+
+        Some(Self { line, content })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_markdown, Element, Line, MarkdownNode, Reply, Segment};
+
+    #[test]
+    fn elements() {
+        let markdown = parse_markdown("# a\n- [b](https://example.com) `<https://c.com>`");
+        assert!(matches!(
+            markdown.elements.as_slice(),
+            [
+                Element::Line(Line::Heading(1)),
+                Element::Line(Line::ListItem {
+                    indent: 0,
+                    number: None
+                }),
+                Element::MaskedLink {
+                    url: "https://example.com",
+                    ..
+                },
+            ]
+        ));
+    }
+
+    #[test]
+    fn segments() {
+        let markdown = parse_markdown("a <https://b.com> c");
+        let text = match &*markdown.nodes[0].read().unwrap() {
+            MarkdownNode::Text(text) => text.clone(),
+            node => panic!("unexpected node {:?}", node),
+        };
+        assert!(matches!(
+            markdown.segments(&text).collect::<Vec<_>>().as_slice(),
+            [
+                Segment::Text("a "),
+                Segment::Element(Element::SuppressedLink("https://b.com")),
+                Segment::Text(" c"),
+            ]
+        ));
+    }
+
+    #[test]
+    fn reply() {
+        let reply = Reply::parse(">3 text").unwrap();
+        assert_eq!(reply.line, 3);
+        assert_eq!(reply.content, "text");
+    }
+
+    #[test]
+    fn reply_without_text() {
+        assert!(Reply::parse(">3").is_none());
+        assert!(Reply::parse(">3 ").is_none());
+    }
+
+    #[test]
+    fn quote_is_not_reply() {
+        assert!(Reply::parse("> quote").is_none());
+        assert!(Reply::parse(">quote").is_none());
+    }
+}
@@ -4,7 +4,10 @@ use crate::{
     instance::Instance,
     nicklist::Nicklist,
     refcell::RefCell,
-    twilight_utils::ext::{ChannelExt, MessageExt},
+    twilight_utils::{
+        content::AmbiguousMention,
+        ext::{ChannelExt, MessageExt},
+    },
     weecord_renderer::{WeecordMessage, WeecordRenderer},
 };
 use parsing::{Emoji, LineEdit};
@@ -13,9 +16,16 @@ use std::{borrow::Cow, rc::Rc};
 use twilight_cache_inmemory::{model::CachedGuild as TwilightGuild, InMemoryCache};
 use twilight_http::request::channel::reaction::RequestReactionType;
 use twilight_model::{
-    channel::{message::MessageReaction, Channel as TwilightChannel, Message, Reaction},
+    channel::{
+        message::{
+            allowed_mentions::{AllowedMentions, ParseTypes},
+            MessageReaction,
+        },
+        Channel as TwilightChannel, Message, Reaction,
+    },
     gateway::payload::incoming::{MemberListItem, MessageUpdate},
     guild::Permissions,
+    http::attachment::Attachment,
     id::{
         marker::{ChannelMarker, GuildMarker, MessageMarker, UserMarker},
         Id,
@@ -39,6 +49,7 @@ impl ChannelBuffer {
         topic: Option<String>,
         nick: &str,
         guild_name: &str,
+        parent_name: Option<&str>,
         id: Id<ChannelMarker>,
         guild_id: Id<GuildMarker>,
         conn: &ConnectionInner,
@@ -47,7 +58,7 @@ impl ChannelBuffer {
     ) -> anyhow::Result<Self> {
         let clean_guild_name = crate::utils::clean_name(guild_name);
         let clean_channel_name = crate::utils::clean_name(name);
-        let buffer_name = format!("discord.{}.{}", clean_guild_name, clean_channel_name);
+        let buffer_name = Self::guild_buffer_name(guild_name, parent_name, name);
 
         let weechat = unsafe { Weechat::weechat() };
 
@@ -99,6 +110,8 @@ impl ChannelBuffer {
 
         if let Some(topic) = topic {
             buffer.set_title(&topic);
+        } else if let Some(parent_name) = parent_name {
+            buffer.set_title(&format!("Thread in #{}", parent_name));
         }
         buffer.set_short_name(&format!("#{}", name));
         buffer.set_localvar("type", "channel");
@@ -114,7 +127,7 @@ impl ChannelBuffer {
         let handle = Rc::new(handle);
         Ok(Self {
             renderer: WeecordRenderer::new(conn, Rc::clone(&handle), config),
-            nicklist: Nicklist::new(conn, Some(guild_id), handle),
+            nicklist: Nicklist::new(conn, config, Some(guild_id), handle),
         })
     }
 
@@ -189,10 +202,62 @@ impl ChannelBuffer {
         let handle = Rc::new(handle);
         Ok(Self {
             renderer: WeecordRenderer::new(conn, Rc::clone(&handle), config),
-            nicklist: Nicklist::new(conn, None, handle),
+            nicklist: Nicklist::new(conn, config, None, handle),
         })
     }
 
+    /// The full name of a guild channel buffer, threads are nested under their parent channel
+    fn guild_buffer_name(guild_name: &str, parent_name: Option<&str>, name: &str) -> String {
+        let clean_guild_name = crate::utils::clean_name(guild_name);
+        let clean_channel_name = crate::utils::clean_name(name);
+        match parent_name {
+            Some(parent_name) => format!(
+                "discord.{}.{}.{}",
+                clean_guild_name,
+                crate::utils::clean_name(parent_name),
+                clean_channel_name
+            ),
+            None => format!("discord.{}.{}", clean_guild_name, clean_channel_name),
+        }
+    }
+
+    /// Rename the buffer of a thread and reflect whether it is archived in its title
+    fn update_thread(&self, name: &str, guild_name: &str, parent_name: &str, archived: bool) {
+        let buffer = match self.renderer.buffer_handle().upgrade() {
+            Ok(buffer) => buffer,
+            Err(_) => return,
+        };
+
+        let was_archived = buffer
+            .get_localvar("archived")
+            .map_or(false, |archived| archived == "true");
+        if archived != was_archived {
+            buffer.set_localvar("archived", if archived { "true" } else { "false" });
+            buffer.print(if archived {
+                "discord: this thread has been archived"
+            } else {
+                "discord: this thread has been unarchived"
+            });
+        }
+
+        buffer.set_name(&Self::guild_buffer_name(
+            guild_name,
+            Some(parent_name),
+            name,
+        ));
+        buffer.set_short_name(&format!("#{}", name));
+        buffer.set_localvar("channel", &crate::utils::clean_name(name));
+        buffer.set_title(&format!(
+            "{} in #{}",
+            if archived {
+                "Archived thread"
+            } else {
+                "Thread"
+            },
+            parent_name
+        ));
+    }
+
     fn nick(cache: &InMemoryCache) -> String {
         format!(
             "@{}",
@@ -266,7 +331,7 @@ impl ChannelBuffer {
                 });
             }
         });
-        self.renderer.redraw_buffer(&[]);
+        self.renderer.schedule_refresh(reaction.message_id);
     }
 
     pub fn remove_reaction(&self, reaction: &Reaction) {
@@ -285,7 +350,7 @@ impl ChannelBuffer {
                 }
             }
         });
-        self.renderer.redraw_buffer(&[]);
+        self.renderer.schedule_refresh(reaction.message_id);
     }
 
     pub fn remove_msg(&self, id: Id<MessageMarker>) {
@@ -299,6 +364,35 @@ impl ChannelBuffer {
     pub fn redraw_buffer(&self, ignore_users: &[Id<UserMarker>]) {
         self.renderer.redraw_buffer(ignore_users);
     }
+
+    pub fn schedule_redraw(&self, ignore_users: &[Id<UserMarker>]) {
+        self.renderer.schedule_redraw(ignore_users);
+    }
+
+    pub fn reveal_spoilers(&self, index: usize) -> bool {
+        self.renderer.reveal_spoilers(index)
+    }
+
+    pub fn toggle_reveal_all_spoilers(&self) -> bool {
+        self.renderer.toggle_reveal_all_spoilers()
+    }
+
+    /// Authors of the loaded messages, most recent first
+    pub fn recent_authors(&self) -> Vec<Id<UserMarker>> {
+        let mut authors = Vec::new();
+        for msg in self.renderer.messages().borrow().iter().rev() {
+            let author = match msg {
+                WeecordMessage::Text(msg) => msg.author.id,
+                #[cfg(feature = "images")]
+                WeecordMessage::Image { msg, .. } => msg.author.id,
+                _ => continue,
+            };
+            if !authors.contains(&author) {
+                authors.push(author);
+            }
+        }
+        authors
+    }
 }
 
 struct ChannelInner {
@@ -344,6 +438,10 @@ impl Channel {
         (Rc::strong_count(&self.inner), Rc::weak_count(&self.inner))
     }
 
+    pub fn guild_id(&self) -> Option<Id<GuildMarker>> {
+        self.guild_id
+    }
+
     pub fn guild(
         channel: &TwilightChannel,
         guild: &TwilightGuild,
@@ -355,21 +453,21 @@ impl Channel {
             "@{}",
             crate::twilight_utils::current_user_nick(guild, &conn.cache).build()
         );
-        let channel_name = config
-            .guilds()
-            .get(&guild.id())
-            .and_then(|g| g.channel_renames().get(&channel.id).cloned())
-            .unwrap_or_else(|| {
-                channel
-                    .name
-                    .clone()
-                    .unwrap_or_else(|| String::from("unknown name"))
-            });
+        let channel_name = display_name(config, guild.id(), channel);
+        let parent_name = if channel.is_thread() {
+            channel
+                .parent_id
+                .and_then(|parent_id| conn.cache.channel(parent_id))
+                .map(|parent| display_name(config, guild.id(), &parent))
+        } else {
+            None
+        };
         let channel_buffer = ChannelBuffer::guild(
             &channel_name,
             channel.topic.clone(),
             &nick,
             guild.name(),
+            parent_name.as_deref(),
             channel.id,
             guild.id(),
             conn,
@@ -409,6 +507,23 @@ impl Channel {
         })
     }
 
+    /// Print a plain line to the channel buffer
+    pub fn print(&self, message: &str) {
+        let handle = self.inner.borrow().buffer.renderer.buffer_handle();
+        if let Ok(buffer) = handle.upgrade() {
+            buffer.print(message);
+        }
+    }
+
+    /// Close the channel buffer, the buffer close signal removes it from the instance
+    pub fn close(&self) {
+        // The inner ref must not be held while closing as the close signal borrows it mutably
+        let handle = self.inner.borrow().buffer.renderer.buffer_handle();
+        if let Ok(buffer) = handle.upgrade() {
+            buffer.close();
+        }
+    }
+
     pub fn mark_unread(&self, mention: bool) {
         self.inner
             .borrow()
@@ -518,9 +633,76 @@ impl Channel {
         // but this is a best attempt
         let token = result.model().await?;
         conn.cache.set_ack_token(&token.token);
+        // The gateway echoes our own ack back, which is then a no-op
+        self.inner
+            .borrow()
+            .buffer
+            .renderer
+            .set_last_read_id(last_displayed_id);
         Ok(())
     }
 
+    /// Apply an ack from the gateway, which is sent when the channel is read on another client
+    pub fn mark_read(&self, message_id: Id<MessageMarker>) {
+        self.inner.borrow().buffer.renderer.mark_read(message_id);
+    }
+
+    /// Upload a file to the channel, showing a local echo until the real message arrives
+    pub async fn upload(&self, filename: String, file: Vec<u8>, caption: &str) {
+        let conn = self.inner.borrow().conn.clone();
+        let (caption, ambiguous) = crate::twilight_utils::content::create_mentions(
+            &conn.cache,
+            self.guild_id,
+            self.id,
+            caption,
+        );
+        self.print_ambiguous_mentions(&ambiguous);
+
+        // Create a nonce to associate the local echo with the incoming message
+        let nonce = thread_rng().gen_range(0..=i64::MAX as u64);
+        self.inner
+            .borrow()
+            .buffer
+            .renderer
+            .add_msg(&WeecordMessage::new_upload_echo(
+                self.guild_id,
+                caption.clone(),
+                nonce,
+                filename.clone(),
+            ));
+
+        let result: anyhow::Result<_> = conn
+            .rt
+            .spawn({
+                let id = self.id;
+                let http = conn.http.clone();
+                let filename = filename.clone();
+                async move {
+                    let attachments = [Attachment::from_bytes(filename, file, 0)];
+                    let mut create_message = http
+                        .create_message(id)
+                        .nonce(nonce)
+                        .attachments(&attachments)?;
+                    if !caption.is_empty() {
+                        create_message = create_message.content(&caption)?;
+                    }
+                    create_message.exec().await?;
+                    Ok(())
+                }
+            })
+            .await
+            .expect("Task is never aborted");
+
+        if let Err(e) = result {
+            tracing::error!(channel.id=?self.id, "Failed to upload file: {:?}", e);
+            self.inner.borrow().buffer.remove_msg(Id::new(nonce));
+            self.print(&format!(
+                "discord: an error occurred uploading \"{}\": {}",
+                filename, e
+            ));
+        }
+    }
+
     pub fn load_users(&self, instance: &Instance) -> anyhow::Result<()> {
         let conn = self.inner.borrow().conn.clone();
         if let Some(guild_id) = self.guild_id {
@@ -559,6 +741,54 @@ impl Channel {
         self.inner.borrow().buffer.redraw_buffer(ignore_users);
     }
 
+    /// Redraw the buffer with the next flush of pending redraws
+    pub fn schedule_redraw(&self, ignore_users: &[Id<UserMarker>]) {
+        self.inner.borrow().buffer.schedule_redraw(ignore_users);
+    }
+
+    pub fn recent_authors(&self) -> Vec<Id<UserMarker>> {
+        self.inner.borrow().buffer.recent_authors()
+    }
+
+    /// Reveal the spoilers of the nth most recent message, starting from 1
+    pub fn reveal_spoilers(&self, line: usize) -> bool {
+        line > 0 && self.inner.borrow().buffer.reveal_spoilers(line - 1)
+    }
+
+    pub fn toggle_reveal_all_spoilers(&self) -> bool {
+        self.inner.borrow().buffer.toggle_reveal_all_spoilers()
+    }
+
+    /// Apply an update of the thread this buffer is for
+    pub fn update_thread(&self, thread: &TwilightChannel, cache: &InMemoryCache) {
+        let guild_id = match self.guild_id {
+            Some(guild_id) => guild_id,
+            None => return,
+        };
+        let guild_name = match cache.guild(guild_id) {
+            Some(guild) => guild.name().to_owned(),
+            None => return,
+        };
+        let parent_name = match thread
+            .parent_id
+            .and_then(|parent_id| cache.channel(parent_id))
+        {
+            Some(parent) => display_name(&self.config, guild_id, &parent),
+            None => return,
+        };
+        let archived = thread
+            .thread_metadata
+            .as_ref()
+            .map_or(false, |metadata| metadata.archived);
+
+        self.inner.borrow().buffer.update_thread(
+            &display_name(&self.config, guild_id, thread),
+            &guild_name,
+            &parent_name,
+            archived,
+        );
+    }
+
     pub fn set_closed(&self) {
         let _ = self
             .inner
@@ -566,11 +796,36 @@ impl Channel {
             .map(|mut inner| inner.closed = true);
     }
 
+    /// Report mentions which matched several members and were sent as plain text
+    fn print_ambiguous_mentions(&self, ambiguous: &[AmbiguousMention]) {
+        for mention in ambiguous {
+            self.print(&format!(
+                "discord: {} is ambiguous ({}), it was not converted to a mention",
+                mention.name,
+                mention.candidates.join(", ")
+            ));
+        }
+    }
+
     pub fn update_nicklist(&self, member_list: &[MemberListItem]) {
         self.inner.borrow().buffer.nicklist.update(member_list);
     }
 }
 
+/// The name of a guild channel, as renamed in the config
+fn display_name(config: &Config, guild_id: Id<GuildMarker>, channel: &TwilightChannel) -> String {
+    config
+        .guilds()
+        .get(&guild_id)
+        .and_then(|g| g.channel_renames().get(&channel.id).cloned())
+        .unwrap_or_else(|| {
+            channel
+                .name
+                .clone()
+                .unwrap_or_else(|| String::from("unknown name"))
+        })
+}
+
 #[allow(clippy::too_many_lines)]
 fn send_message(channel: &Channel, conn: &ConnectionInner, input: &str) {
     let channel = channel.clone();
@@ -579,7 +834,9 @@ fn send_message(channel: &Channel, conn: &ConnectionInner, input: &str) {
     let conn = conn.clone();
     let cache = conn.cache.clone();
     let http = conn.http.clone();
-    let input = crate::twilight_utils::content::create_mentions(&cache, guild_id, input);
+    let (input, ambiguous) =
+        crate::twilight_utils::content::create_mentions(&cache, guild_id, id, input);
+    channel.print_ambiguous_mentions(&ambiguous);
     match parsing::LineEdit::parse(&input) {
         Some(LineEdit::Sub {
             line,
@@ -750,6 +1007,34 @@ fn send_message(channel: &Channel, conn: &ConnectionInner, input: &str) {
                 return;
             };
 
+            let (content, reply) = match parsing::Reply::parse(&input) {
+                Some(reply) => {
+                    let msg = match channel
+                        .inner
+                        .borrow()
+                        .buffer
+                        .renderer
+                        .get_nth_message(reply.line - 1)
+                    {
+                        Some(WeecordMessage::Text(msg)) => msg,
+                        #[cfg(feature = "images")]
+                        Some(WeecordMessage::Image { msg, .. }) => msg,
+                        Some(WeecordMessage::LocalEcho { .. }) => return,
+                        Some(WeecordMessage::Notification { .. }) => return,
+                        None => {
+                            tracing::warn!("Unable to find message n {}", reply.line);
+                            Weechat::print(&format!(
+                                "discord: unable to locate message n = {}",
+                                reply.line
+                            ));
+                            return;
+                        },
+                    };
+                    (reply.content.to_owned(), Some(*msg))
+                },
+                None => (input, None),
+            };
+
             if let Some(can_send) = cache
                 .channel(channel.id)
                 .and_then(|channel| channel.can_send(&cache))
@@ -767,10 +1052,23 @@ fn send_message(channel: &Channel, conn: &ConnectionInner, input: &str) {
 
             // Create a nonce to associate the local echo with the incoming message
             let nonce = thread_rng().gen_range(0..=i64::MAX as u64);
+            let reply_ping = channel.config.reply_ping();
             conn.rt.spawn({
-                let input = input.clone();
+                let content = content.clone();
+                let reply_id = reply.as_ref().map(|msg| msg.id);
                 async move {
-                    match http.create_message(id).nonce(nonce).content(&input) {
+                    let allowed_mentions = AllowedMentions {
+                        parse: vec![ParseTypes::Everyone, ParseTypes::Roles, ParseTypes::Users],
+                        replied_user: reply_ping,
+                        ..AllowedMentions::default()
+                    };
+                    let mut create_message = http.create_message(id).nonce(nonce);
+                    if let Some(reply_id) = reply_id {
+                        create_message = create_message
+                            .reply(reply_id)
+                            .allowed_mentions(Some(&allowed_mentions));
+                    }
+                    match create_message.content(&content) {
                         Ok(msg) => {
                             if let Err(e) = msg.exec().await {
                                 tracing::error!("Failed to send message: {:?}", e);
@@ -796,7 +1094,7 @@ fn send_message(channel: &Channel, conn: &ConnectionInner, input: &str) {
                 .borrow()
                 .buffer
                 .renderer
-                .add_msg(&WeecordMessage::new_echo(guild_id, input, nonce));
+                .add_msg(&WeecordMessage::new_echo(guild_id, content, nonce, reply));
         },
     };
 }
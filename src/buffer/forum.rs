@@ -0,0 +1,385 @@
+use crate::{
+    discord::{
+        discord_connection::ConnectionInner,
+        forum::{
+            CreateForumPost, CreateForumPostMessage, ForumChannel, ForumPost, ForumTag,
+            ThreadsListing,
+        },
+    },
+    instance::Instance,
+    refcell::RefCell,
+    twilight_utils::ext::ChannelExt,
+    weechat2::{Style, StyledString},
+};
+use std::{borrow::Cow, rc::Rc};
+use twilight_cache_inmemory::model::CachedGuild as TwilightGuild;
+use twilight_http::{request::Request, routing::Route};
+use twilight_model::{
+    channel::Channel,
+    id::{
+        marker::{ChannelMarker, GuildMarker},
+        Id,
+    },
+};
+use weechat::{
+    buffer::{Buffer, BufferBuilder, BufferHandle},
+    Weechat,
+};
+
+pub struct ForumBuffer(BufferHandle);
+
+impl ForumBuffer {
+    pub fn new(
+        channel: &Channel,
+        guild: &TwilightGuild,
+        instance: &Instance,
+    ) -> anyhow::Result<Self> {
+        let clean_guild_name = crate::utils::clean_name(guild.name());
+        let clean_channel_name = crate::utils::clean_name(&channel.name());
+        let buffer_name = format!("discord.{}.{}", clean_guild_name, clean_channel_name);
+
+        let weechat = unsafe { Weechat::weechat() };
+
+        if let Some(buffer) = weechat.buffer_search(crate::PLUGIN_NAME, &buffer_name) {
+            buffer.close();
+        };
+
+        let channel_id = channel.id;
+        let guild_id = guild.id();
+        let handle = BufferBuilder::new(&buffer_name)
+            .input_callback({
+                let instance = instance.clone();
+                move |_: &Weechat, _: &Buffer, input: Cow<str>| {
+                    let forum = instance.borrow_forums().get(&channel_id).cloned();
+                    if let Some(forum) = forum {
+                        forum.handle_input(&input);
+                    }
+                    Ok(())
+                }
+            })
+            .close_callback({
+                let name = format!("Forum #{}", channel.name());
+                move |_: &Weechat, _: &Buffer| {
+                    tracing::trace!(guild.id=?guild_id, channel.id=?channel_id, buffer.name=%name, "Forum buffer close");
+                    Ok(())
+                }
+            })
+            .build()
+            .map_err(|_| anyhow::anyhow!("Unable to create forum buffer"))?;
+
+        let buffer = handle
+            .upgrade()
+            .map_err(|_| anyhow::anyhow!("Unable to create forum buffer"))?;
+
+        buffer.set_short_name(&format!("#{}", channel.name()));
+        if let Some(topic) = channel.topic.as_ref() {
+            buffer.set_title(topic);
+        }
+        buffer.set_localvar("guild_id", &guild_id.to_string());
+        buffer.set_localvar("channel_id", &channel_id.to_string());
+        buffer.set_localvar("weecord_type", "forum");
+        buffer.set_localvar("type", "channel");
+        buffer.set_localvar("server", &clean_guild_name);
+
+        Ok(ForumBuffer(handle))
+    }
+}
+
+pub struct ForumInner {
+    conn: ConnectionInner,
+    instance: Instance,
+    buffer: Option<ForumBuffer>,
+    tags: Vec<ForumTag>,
+    posts: Vec<ForumPost>,
+    closed: bool,
+}
+
+impl Drop for ForumInner {
+    fn drop(&mut self) {
+        // This feels ugly, but without it, closing a buffer causes this struct to drop, which in turn
+        // causes a segfault (for some reason)
+        if self.closed {
+            return;
+        }
+        if let Some(buffer) = self.buffer.as_ref() {
+            if let Ok(buffer) = buffer.0.upgrade() {
+                buffer.close();
+            }
+        }
+    }
+}
+
+impl ForumInner {
+    pub fn new(conn: ConnectionInner, instance: Instance) -> Self {
+        Self {
+            conn,
+            instance,
+            buffer: None,
+            tags: Vec::new(),
+            posts: Vec::new(),
+            closed: false,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Forum {
+    pub(crate) guild_id: Id<GuildMarker>,
+    pub(crate) channel_id: Id<ChannelMarker>,
+    inner: Rc<RefCell<ForumInner>>,
+}
+
+impl Forum {
+    pub fn debug_counts(&self) -> (usize, usize) {
+        (Rc::strong_count(&self.inner), Rc::weak_count(&self.inner))
+    }
+
+    pub fn new(
+        guild_id: Id<GuildMarker>,
+        channel_id: Id<ChannelMarker>,
+        conn: ConnectionInner,
+        instance: Instance,
+    ) -> Self {
+        let inner = Rc::new(RefCell::new(ForumInner::new(conn, instance)));
+        Forum {
+            guild_id,
+            channel_id,
+            inner,
+        }
+    }
+
+    /// Create the forum buffer and list the posts
+    pub async fn load(&self) -> anyhow::Result<()> {
+        tracing::trace!(guild.id=?self.guild_id, channel.id=?self.channel_id, "Loading forum");
+        let conn = self.inner.borrow().conn.clone();
+        let instance = self.inner.borrow().instance.clone();
+        let cache = &conn.cache;
+
+        let forum_buffer = match (cache.guild(self.guild_id), cache.channel(self.channel_id)) {
+            (Some(guild), Some(channel)) => ForumBuffer::new(&channel, &guild, &instance),
+            _ => Err(anyhow::anyhow!("Unable to find forum channel")),
+        }?;
+        self.inner.borrow_mut().buffer.replace(forum_buffer);
+
+        self.refresh().await
+    }
+
+    /// Fetch the forum tags and posts and redraw the buffer
+    pub async fn refresh(&self) -> anyhow::Result<()> {
+        let conn = self.inner.borrow().conn.clone();
+
+        let result: anyhow::Result<_> = conn
+            .rt
+            .spawn({
+                let guild_id = self.guild_id;
+                let channel_id = self.channel_id;
+                let http = conn.http.clone();
+                async move {
+                    let forum: ForumChannel = serde_json::from_slice(
+                        &http.channel(channel_id).exec().await?.bytes().await?,
+                    )?;
+                    let active: ThreadsListing = serde_json::from_slice(
+                        &http.active_threads(guild_id).exec().await?.bytes().await?,
+                    )?;
+                    let archived: ThreadsListing = serde_json::from_slice(
+                        &http
+                            .public_archived_threads(channel_id)
+                            .exec()
+                            .await?
+                            .bytes()
+                            .await?,
+                    )?;
+
+                    let mut posts: Vec<_> = active
+                        .threads
+                        .into_iter()
+                        .filter(|post| post.parent_id == Some(channel_id))
+                        .chain(archived.threads)
+                        .collect();
+                    posts.sort_by_key(|post| {
+                        std::cmp::Reverse(post.last_message_id.map_or(post.id.get(), Id::get))
+                    });
+                    posts.dedup_by_key(|post| post.id);
+                    Ok((forum.available_tags, posts))
+                }
+            })
+            .await
+            .expect("Task is never aborted");
+        let (tags, posts) = result?;
+
+        {
+            let mut inner = self.inner.borrow_mut();
+            inner.tags = tags;
+            inner.posts = posts;
+        }
+        self.redraw();
+        Ok(())
+    }
+
+    fn redraw(&self) {
+        let inner = self.inner.borrow();
+        let buffer = match inner.buffer.as_ref().map(|buffer| buffer.0.upgrade()) {
+            Some(Ok(buffer)) => buffer,
+            _ => return,
+        };
+        buffer.clear();
+
+        if inner.posts.is_empty() {
+            buffer.print(
+                "No posts, start one by entering its body and running /discord forum post <title>",
+            );
+            return;
+        }
+        buffer.print(
+            "Enter the number of a post to open it, or start one by entering its body and running \
+             /discord forum post <title>",
+        );
+
+        for (i, post) in inner.posts.iter().enumerate() {
+            let tags: Vec<_> = post
+                .applied_tags
+                .iter()
+                .filter_map(|tag_id| inner.tags.iter().find(|tag| tag.id == *tag_id))
+                .map(|tag| match &tag.emoji_name {
+                    Some(emoji) => format!("{} {}", emoji, tag.name),
+                    None => tag.name.clone(),
+                })
+                .collect();
+            let author = post
+                .owner_id
+                .and_then(|owner_id| inner.conn.cache.user(owner_id))
+                .map_or_else(|| "unknown user".to_owned(), |user| user.name.clone());
+            let last_activity = crate::twilight_utils::snowflake_timestamp(
+                post.last_message_id.map_or(post.id.get(), Id::get),
+            );
+
+            let mut line = StyledString::new();
+            line.push_styled_str(Style::color("8"), &format!("{:>3}. ", i + 1))
+                .push_styled_str(Style::Bold, &post.name);
+            if !tags.is_empty() {
+                line.push_str(" ")
+                    .push_styled_str(Style::color("cyan"), &format!("[{}]", tags.join(", ")));
+            }
+            line.push_styled_str(
+                Style::color("8"),
+                &format!(
+                    " by {}, {} replies, active {}",
+                    author,
+                    post.message_count.unwrap_or(0),
+                    crate::utils::fmt_timestamp(last_activity, 'R')
+                ),
+            );
+            buffer.print(&line.build());
+        }
+    }
+
+    fn handle_input(&self, input: &str) {
+        let post = input
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| n.checked_sub(1))
+            .and_then(|index| self.inner.borrow().posts.get(index).cloned());
+        match post {
+            Some(post) => {
+                let forum = self.clone();
+                Weechat::spawn(async move {
+                    if let Err(e) = forum.open_post(post.id).await {
+                        tracing::error!(thread.id=?post.id, "Unable to open forum post: {}", e);
+                        forum.print(&format!("discord: unable to open \"{}\": {}", post.name, e));
+                    }
+                })
+                .detach();
+            },
+            None => self.print("discord: enter the number of a post to open it"),
+        }
+    }
+
+    /// Publish a new post and open it
+    pub fn post(&self, title: String, body: String) {
+        let forum = self.clone();
+        Weechat::spawn(async move {
+            if let Err(e) = forum.create_post(&title, &body).await {
+                tracing::error!(channel.id=?forum.channel_id, "Unable to create forum post: {}", e);
+                forum.print(&format!("discord: unable to create post: {}", e));
+            }
+        })
+        .detach();
+    }
+
+    async fn create_post(&self, title: &str, body: &str) -> anyhow::Result<()> {
+        let conn = self.inner.borrow().conn.clone();
+        let result: anyhow::Result<ForumPost> = conn
+            .rt
+            .spawn({
+                let channel_id = self.channel_id;
+                let http = conn.http.clone();
+                let request = Request::builder(&Route::CreateThread {
+                    channel_id: channel_id.get(),
+                })
+                .json(&CreateForumPost {
+                    name: title,
+                    message: CreateForumPostMessage { content: body },
+                })
+                .map(|builder| builder.build());
+                async move { Ok(http.request(request?).await?.model().await?) }
+            })
+            .await
+            .expect("Task is never aborted");
+        let post = result?;
+
+        self.refresh().await?;
+        self.open_post(post.id).await
+    }
+
+    /// Open a post as a thread buffer
+    async fn open_post(&self, post_id: Id<ChannelMarker>) -> anyhow::Result<()> {
+        let (conn, instance) = {
+            let inner = self.inner.borrow();
+            (inner.conn.clone(), inner.instance.clone())
+        };
+
+        let guild = match instance.borrow_guilds().get(&self.guild_id) {
+            Some(guild) => guild.clone(),
+            None => anyhow::bail!("server has not been added to weechat"),
+        };
+        if instance
+            .search_buffer(Some(self.guild_id), post_id)
+            .is_some()
+        {
+            return Ok(());
+        }
+
+        // Archived posts are not in the cache
+        let thread = match conn.cache.channel(post_id) {
+            Some(thread) => thread.value().clone(),
+            None => {
+                let result: anyhow::Result<_> = conn
+                    .rt
+                    .spawn({
+                        let http = conn.http.clone();
+                        async move { Ok(http.channel(post_id).exec().await?.model().await?) }
+                    })
+                    .await
+                    .expect("Task is never aborted");
+                let mut thread: Channel = result?;
+                thread.guild_id = Some(self.guild_id);
+                thread
+            },
+        };
+
+        guild.join_channel(&thread)?;
+        Ok(())
+    }
+
+    fn print(&self, message: &str) {
+        let inner = self.inner.borrow();
+        if let Some(Ok(buffer)) = inner.buffer.as_ref().map(|buffer| buffer.0.upgrade()) {
+            buffer.print(message);
+        }
+    }
+
+    pub fn set_closed(&self) {
+        self.inner.borrow_mut().closed = true;
+    }
+}
@@ -230,7 +230,10 @@ impl Guild {
             if let Some(guild_channels) = conn.cache.guild_channels(self.id) {
                 for channel_id in guild_channels.iter() {
                     if let Some(cached_channel) = conn.cache.channel(*channel_id) {
-                        if cached_channel.is_text_channel(&conn.cache) {
+                        // Threads are only joined explicitly
+                        if cached_channel.is_text_channel(&conn.cache)
+                            && !cached_channel.is_thread()
+                        {
                             tracing::info!(
                                 "Joining discord mode channel: #{}",
                                 cached_channel.name()
@@ -294,12 +297,7 @@ impl Guild {
             &inner.instance,
         )?;
 
-        {
-            let _old = inner
-                .instance
-                .borrow_channels_mut()
-                .insert(channel_id, channel.clone());
-        }
+        let _old = inner.instance.insert_channel(channel_id, channel.clone());
         inner.channels.insert(channel_id);
 
         if let Some(read_state) = inner.conn.cache.read_state(channel_id) {
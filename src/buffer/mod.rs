@@ -1,5 +1,6 @@
 pub mod channel;
 pub mod debug;
 pub mod ext;
+pub mod forum;
 pub mod guild;
 pub mod pins;
@@ -7,8 +7,9 @@ use std::rc::Rc;
 use twilight_cache_inmemory::model::CachedGuild as TwilightGuild;
 use twilight_model::{
     channel::Channel,
+    gateway::payload::incoming::MessageUpdate,
     id::{
-        marker::{ChannelMarker, GuildMarker},
+        marker::{ChannelMarker, GuildMarker, MessageMarker},
         Id,
     },
     user::User,
@@ -234,6 +235,18 @@ impl Pins {
         Ok(())
     }
 
+    pub fn update_message(&self, update: MessageUpdate) {
+        if let Some(buffer) = self.inner.borrow().buffer.as_ref() {
+            buffer.0.apply_message_update(update);
+        }
+    }
+
+    pub fn remove_message(&self, id: Id<MessageMarker>) {
+        if let Some(buffer) = self.inner.borrow().buffer.as_ref() {
+            buffer.0.remove_msg(id);
+        }
+    }
+
     pub fn set_closed(&self) {
         self.inner.borrow_mut().closed = true;
     }
@@ -1,7 +1,9 @@
 use crate::{
     config::Config,
+    discord::notification_settings::NotificationLevel,
     refcell::{RefCell, RefMut},
 };
+use serde::{de::DeserializeOwned, Serialize};
 use std::{
     borrow::Cow,
     collections::HashMap,
@@ -22,6 +24,8 @@ pub struct GuildConfigInner {
     autojoin: Vec<Id<ChannelMarker>>,
     watched: Vec<Id<ChannelMarker>>,
     channel_renames: HashMap<Id<ChannelMarker>, String>,
+    notification_overrides: HashMap<Id<ChannelMarker>, NotificationLevel>,
+    send_typing: Option<bool>,
 }
 
 impl GuildConfigInner {
@@ -31,6 +35,8 @@ impl GuildConfigInner {
             autojoin: Vec::new(),
             watched: Vec::new(),
             channel_renames: HashMap::new(),
+            notification_overrides: HashMap::new(),
+            send_typing: None,
         }
     }
 }
@@ -105,7 +111,7 @@ impl GuildConfig {
             .description("The mapping of channels to rename in weechat")
             .default_value("{}")
             .set_check_callback(|_: &Weechat, _: &StringOption, value: Cow<str>| {
-                Self::parse_channel_id_mapping(&value).is_ok()
+                Self::parse_channel_id_mapping::<String>(&value).is_ok()
             })
             .set_change_callback(move |_, option| {
                 let inner = inner_clone.upgrade().expect("Config has outlived guild");
@@ -118,18 +124,72 @@ impl GuildConfig {
             .new_string_option(channel_renames)
             .expect("Unable to create channel renames option");
 
+        let inner_clone = Weak::clone(&weak_inner);
+        let notification_overrides =
+            StringOptionSettings::new(format!("{}.notification_overrides", id))
+                .description(
+                    "The mapping of channels to a notification level (all, mentions or nothing), \
+                     taking precedence over the discord notification settings",
+                )
+                .default_value("{}")
+                .set_check_callback(|_: &Weechat, _: &StringOption, value: Cow<str>| {
+                    Self::parse_channel_id_mapping::<NotificationLevel>(&value).is_ok()
+                })
+                .set_change_callback(move |_, option| {
+                    let inner = inner_clone.upgrade().expect("Config has outlived guild");
+
+                    let overrides =
+                        Self::parse_channel_id_mapping(&option.value()).unwrap_or_default();
+
+                    inner.borrow_mut().notification_overrides = overrides;
+                });
+        guild_section
+            .new_string_option(notification_overrides)
+            .expect("Unable to create notification overrides option");
+
+        let inner_clone = Weak::clone(&weak_inner);
+        let send_typing = StringOptionSettings::new(format!("{}.send_typing", id))
+            .description(
+                "Should typing status be sent to this guild (on or off), empty to use \
+                 weecord.general.send_typing",
+            )
+            .default_value("")
+            .set_check_callback(|_: &Weechat, _: &StringOption, value: Cow<str>| {
+                Self::parse_send_typing(&value).is_some()
+            })
+            .set_change_callback(move |_, option| {
+                let inner = inner_clone.upgrade().expect("Config has outlived guild");
+
+                inner.borrow_mut().send_typing = Self::parse_send_typing(&option.value()).flatten();
+            });
+        guild_section
+            .new_string_option(send_typing)
+            .expect("Unable to create send typing option");
+
         GuildConfig { inner, id }
     }
 
-    // Parses the channel renames format (current a json map)
-    fn parse_channel_id_mapping(value: &str) -> anyhow::Result<HashMap<Id<ChannelMarker>, String>> {
-        Ok(serde_json::from_str::<HashMap<Id<ChannelMarker>, String>>(
+    /// Parses the send typing override, `Some(None)` if it is unset
+    fn parse_send_typing(value: &str) -> Option<Option<bool>> {
+        match value {
+            "" => Some(None),
+            "on" => Some(Some(true)),
+            "off" => Some(Some(false)),
+            _ => None,
+        }
+    }
+
+    // Parses the channel mapping format (current a json map)
+    fn parse_channel_id_mapping<T: DeserializeOwned>(
+        value: &str,
+    ) -> anyhow::Result<HashMap<Id<ChannelMarker>, T>> {
+        Ok(serde_json::from_str::<HashMap<Id<ChannelMarker>, T>>(
             value,
         )?)
     }
 
-    fn serialize_channel_id_mapping(
-        value: &HashMap<Id<ChannelMarker>, String>,
+    fn serialize_channel_id_mapping<T: Serialize>(
+        value: &HashMap<Id<ChannelMarker>, T>,
     ) -> anyhow::Result<String> {
         Ok(serde_json::to_string(value)?)
     }
@@ -158,6 +218,25 @@ impl GuildConfig {
         self.inner.borrow().channel_renames.clone()
     }
 
+    pub fn notification_override(
+        &self,
+        channel_id: Id<ChannelMarker>,
+    ) -> Option<NotificationLevel> {
+        self.inner
+            .borrow()
+            .notification_overrides
+            .get(&channel_id)
+            .copied()
+    }
+
+    pub fn notification_overrides(&self) -> HashMap<Id<ChannelMarker>, NotificationLevel> {
+        self.inner.borrow().notification_overrides.clone()
+    }
+
+    pub fn send_typing(&self) -> Option<bool> {
+        self.inner.borrow().send_typing
+    }
+
     pub fn persist(&self, config: &Config) {
         let config = config.config.borrow();
         let section = config
@@ -198,9 +277,29 @@ impl GuildConfig {
             false,
         );
 
+        let notification_overrides = section
+            .search_option(&format!("{}.notification_overrides", self.id))
+            .expect("notification overrides option does not exist");
+        notification_overrides.set(
+            &Self::serialize_channel_id_mapping(&self.notification_overrides()).unwrap_or_default(),
+            false,
+        );
+
         let autoconnect = section
             .search_option(&format!("{}.autoconnect", self.id))
             .expect("autoconnect option does not exist");
         autoconnect.set(if self.autoconnect() { "true" } else { "false" }, false);
+
+        let send_typing = section
+            .search_option(&format!("{}.send_typing", self.id))
+            .expect("send typing option does not exist");
+        send_typing.set(
+            match self.send_typing() {
+                Some(true) => "on",
+                Some(false) => "off",
+                None => "",
+            },
+            false,
+        );
     }
 }
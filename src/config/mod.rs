@@ -1,20 +1,28 @@
 //! This module provides Config structs which are isolated from the other data structures to facilitate
 //! better isolation
-use crate::refcell::{RefCell, RefMut};
+use crate::{
+    discord::presence::{self, CustomStatus},
+    refcell::{RefCell, RefMut},
+    utils::emoji::EmojiStyle,
+};
 use anyhow::Result;
 use std::{
     collections::HashMap,
     rc::{Rc, Weak},
 };
 use tracing_subscriber::EnvFilter;
-use twilight_model::id::{
-    marker::{ChannelMarker, GuildMarker},
-    Id,
+use twilight_model::{
+    gateway::presence::Status,
+    id::{
+        marker::{ChannelMarker, GuildMarker},
+        Id,
+    },
 };
 use weechat::{
     config::{
-        BooleanOptionSettings, Conf, Config as WeechatConfig, ConfigSection, ConfigSectionSettings,
-        IntegerOptionSettings, OptionChanged, StringOption, StringOptionSettings,
+        BooleanOptionSettings, ColorOptionSettings, Conf, Config as WeechatConfig, ConfigSection,
+        ConfigSectionSettings, IntegerOptionSettings, OptionChanged, StringOption,
+        StringOptionSettings,
     },
     Weechat,
 };
@@ -58,8 +66,18 @@ pub struct LookConfig {
     pub typing_list_max: i32,
     pub show_formatting_chars: bool,
     pub show_unknown_user_ids: bool,
+    pub highlight_roles: bool,
+    pub highlight_everyone: bool,
+    pub status_prefix: String,
+    pub status_color_online: String,
+    pub status_color_idle: String,
+    pub status_color_dnd: String,
+    pub status_color_offline: String,
     pub message_fetch_count: i32,
     pub readonly_value: String,
+    pub emoji_style: EmojiStyle,
+    pub spoiler_char: String,
+    pub redraw_interval: i32,
     pub image_max_height: i32,
     pub image_charset: Charset,
 }
@@ -70,21 +88,47 @@ impl Default for LookConfig {
             auto_open_tracing: false,
             show_formatting_chars: true,
             show_unknown_user_ids: false,
+            highlight_roles: true,
+            highlight_everyone: true,
+            status_prefix: "•".to_owned(),
+            status_color_online: "green".to_owned(),
+            status_color_idle: "yellow".to_owned(),
+            status_color_dnd: "red".to_owned(),
+            status_color_offline: "darkgray".to_owned(),
             nick_prefix: "".to_owned(),
             nick_suffix: "".to_owned(),
             typing_list_max: 5,
             typing_list_style: 0,
             message_fetch_count: 50,
             readonly_value: "🔒".to_owned(),
+            emoji_style: EmojiStyle::Unicode,
+            spoiler_char: "█".to_owned(),
+            redraw_interval: 100,
             image_max_height: 40,
             image_charset: Charset::Blocks,
         }
     }
 }
 
+/// Colors of rendered messages, "default" leaves an element unstyled
+#[derive(Clone, Debug)]
 pub struct ColorConfig {
     pub nick_prefix_color: String,
     pub nick_suffix_color: String,
+    pub local_echo: String,
+    pub edited: String,
+    pub reactions: String,
+    pub own_reactions: String,
+    pub inline_code: String,
+    pub code_block: String,
+    pub strikethrough: String,
+    pub subtext: String,
+    pub quote_bar: String,
+    pub embed_bar: String,
+    pub spoiler: String,
+    pub mention: String,
+    pub self_mention: String,
+    pub unknown_user: String,
 }
 
 impl Default for ColorConfig {
@@ -92,10 +136,78 @@ impl Default for ColorConfig {
         ColorConfig {
             nick_prefix_color: "".to_owned(),
             nick_suffix_color: "".to_owned(),
+            local_echo: "244".to_owned(),
+            edited: "8".to_owned(),
+            reactions: "8".to_owned(),
+            own_reactions: "8".to_owned(),
+            inline_code: "8".to_owned(),
+            code_block: "8".to_owned(),
+            strikethrough: "red".to_owned(),
+            subtext: "8".to_owned(),
+            quote_bar: "default".to_owned(),
+            embed_bar: "default".to_owned(),
+            spoiler: "default".to_owned(),
+            mention: "default".to_owned(),
+            self_mention: "default".to_owned(),
+            unknown_user: "default".to_owned(),
         }
     }
 }
 
+type ColorField = fn(&mut ColorConfig) -> &mut String;
+
+/// Name, description and field of each option of the color section
+fn color_options() -> [(&'static str, &'static str, ColorField); 14] {
+    [
+        (
+            "local_echo",
+            "Color of messages which are still being sent",
+            |c| &mut c.local_echo,
+        ),
+        ("edited", "Color of the \"(edited)\" marker", |c| {
+            &mut c.edited
+        }),
+        ("reactions", "Color of reactions", |c| &mut c.reactions),
+        ("own_reactions", "Color of reactions you have added", |c| {
+            &mut c.own_reactions
+        }),
+        ("inline_code", "Color of inline code", |c| {
+            &mut c.inline_code
+        }),
+        ("code_block", "Color of code blocks", |c| &mut c.code_block),
+        ("strikethrough", "Color of strikethrough text", |c| {
+            &mut c.strikethrough
+        }),
+        ("subtext", "Color of subtext lines", |c| &mut c.subtext),
+        ("quote_bar", "Color of the bar next to block quotes", |c| {
+            &mut c.quote_bar
+        }),
+        ("embed_bar", "Color of the bar next to embeds", |c| {
+            &mut c.embed_bar
+        }),
+        (
+            "spoiler",
+            "Color of spoilers and the mask of hidden spoilers",
+            |c| &mut c.spoiler,
+        ),
+        (
+            "mention",
+            "Color of user mentions, \"default\" uses the color of the member's role",
+            |c| &mut c.mention,
+        ),
+        (
+            "self_mention",
+            "Color of mentions of yourself, \"default\" uses the mention color",
+            |c| &mut c.self_mention,
+        ),
+        (
+            "unknown_user",
+            "Color of mentions of users which are not cached",
+            |c| &mut c.unknown_user,
+        ),
+    ]
+}
+
 pub struct InnerConfig {
     pub look: LookConfig,
     pub color: ColorConfig,
@@ -107,7 +219,17 @@ pub struct InnerConfig {
     // Should we use value of weechat.history.max_buffer_lines_number here instead?
     pub max_buffer_messages: i32,
     pub send_typing: bool,
+    pub typing_delay: i32,
+    pub typing_interval: i32,
     pub join_all: bool,
+    pub reply_ping: bool,
+    pub status: String,
+    pub custom_status: String,
+    pub custom_status_emoji: String,
+    pub custom_status_expiry: String,
+    pub sync_away: String,
+    /// Called after a color changes, to redraw open buffers
+    pub colors_changed: Option<Rc<dyn Fn()>>,
 }
 
 impl Default for InnerConfig {
@@ -122,7 +244,16 @@ impl Default for InnerConfig {
             watched_private: Vec::new(),
             max_buffer_messages: 4096,
             send_typing: false,
+            typing_delay: 3,
+            typing_interval: 9,
             join_all: false,
+            reply_ping: true,
+            status: "online".to_owned(),
+            custom_status: "".to_owned(),
+            custom_status_emoji: "".to_owned(),
+            custom_status_expiry: "".to_owned(),
+            sync_away: "off".to_owned(),
+            colors_changed: None,
         }
     }
 }
@@ -242,6 +373,43 @@ impl Config {
                 )
                 .expect("Unable to create send typing option");
 
+            let inner_clone = Weak::clone(&inner);
+            general
+                .new_integer_option(
+                    IntegerOptionSettings::new("typing_delay")
+                        .description(
+                            "Seconds of continuous typing before the typing status is sent, a \
+                             pause of two seconds restarts it",
+                        )
+                        .default_value(3)
+                        .min(0)
+                        .max(60)
+                        .set_change_callback(move |_, option| {
+                            let inner = inner_clone
+                                .upgrade()
+                                .expect("Outer config has outlived inner config");
+                            inner.borrow_mut().typing_delay = option.value();
+                        }),
+                )
+                .expect("Unable to create typing delay option");
+
+            let inner_clone = Weak::clone(&inner);
+            general
+                .new_integer_option(
+                    IntegerOptionSettings::new("typing_interval")
+                        .description("Seconds between sending the typing status while typing")
+                        .default_value(9)
+                        .min(1)
+                        .max(60)
+                        .set_change_callback(move |_, option| {
+                            let inner = inner_clone
+                                .upgrade()
+                                .expect("Outer config has outlived inner config");
+                            inner.borrow_mut().typing_interval = option.value();
+                        }),
+                )
+                .expect("Unable to create typing interval option");
+
             let inner_clone = Weak::clone(&inner);
             general
                 .new_boolean_option(
@@ -256,6 +424,107 @@ impl Config {
                         }),
                 )
                 .expect("Unable to create join all option");
+
+            let inner_clone = Weak::clone(&inner);
+            general
+                .new_boolean_option(
+                    BooleanOptionSettings::new("reply_ping")
+                        .description("Should the author of a message be pinged when replying to it")
+                        .default_value(true)
+                        .set_change_callback(move |_, option| {
+                            let inner = inner_clone
+                                .upgrade()
+                                .expect("Outer config has outlived inner config");
+                            inner.borrow_mut().reply_ping = option.value();
+                        }),
+                )
+                .expect("Unable to create reply ping option");
+
+            let inner_clone = Weak::clone(&inner);
+            general
+                .new_string_option(
+                    StringOptionSettings::new("status")
+                        .description("Status to connect with: online, idle, dnd or invisible")
+                        .default_value("online")
+                        .set_check_callback(|_: &Weechat, _: &StringOption, value| {
+                            presence::parse_status(&value).is_some()
+                        })
+                        .set_change_callback(move |_, option| {
+                            let inner = inner_clone
+                                .upgrade()
+                                .expect("Outer config has outlived inner config");
+                            inner.borrow_mut().status = option.value().to_string();
+                        }),
+                )
+                .expect("Unable to create status option");
+
+            let inner_clone = Weak::clone(&inner);
+            general
+                .new_string_option(
+                    StringOptionSettings::new("custom_status")
+                        .description("Text of the custom status")
+                        .set_change_callback(move |_, option| {
+                            let inner = inner_clone
+                                .upgrade()
+                                .expect("Outer config has outlived inner config");
+                            inner.borrow_mut().custom_status = option.value().to_string();
+                        }),
+                )
+                .expect("Unable to create custom status option");
+
+            let inner_clone = Weak::clone(&inner);
+            general
+                .new_string_option(
+                    StringOptionSettings::new("custom_status_emoji")
+                        .description("Unicode emoji of the custom status")
+                        .set_change_callback(move |_, option| {
+                            let inner = inner_clone
+                                .upgrade()
+                                .expect("Outer config has outlived inner config");
+                            inner.borrow_mut().custom_status_emoji = option.value().to_string();
+                        }),
+                )
+                .expect("Unable to create custom status emoji option");
+
+            let inner_clone = Weak::clone(&inner);
+            general
+                .new_string_option(
+                    StringOptionSettings::new("custom_status_expiry")
+                        .description(
+                            "Unix timestamp at which the custom status is cleared, empty for never",
+                        )
+                        .set_check_callback(|_: &Weechat, _: &StringOption, value| {
+                            value.is_empty() || value.parse::<i64>().is_ok()
+                        })
+                        .set_change_callback(move |_, option| {
+                            let inner = inner_clone
+                                .upgrade()
+                                .expect("Outer config has outlived inner config");
+                            inner.borrow_mut().custom_status_expiry = option.value().to_string();
+                        }),
+                )
+                .expect("Unable to create custom status expiry option");
+
+            let inner_clone = Weak::clone(&inner);
+            general
+                .new_string_option(
+                    StringOptionSettings::new("sync_away")
+                        .description(
+                            "Status to set while away in weechat, with the away message as the \
+                             custom status: off, idle or dnd",
+                        )
+                        .default_value("off")
+                        .set_check_callback(|_: &Weechat, _: &StringOption, value| {
+                            matches!(value.as_ref(), "off" | "idle" | "dnd")
+                        })
+                        .set_change_callback(move |_, option| {
+                            let inner = inner_clone
+                                .upgrade()
+                                .expect("Outer config has outlived inner config");
+                            inner.borrow_mut().sync_away = option.value().to_string();
+                        }),
+                )
+                .expect("Unable to create sync away option");
         }
 
         {
@@ -369,6 +638,104 @@ impl Config {
             )
             .expect("Unable to create show unknown user ids option");
 
+            let inner_clone = Weak::clone(&inner);
+            look.new_boolean_option(
+                BooleanOptionSettings::new("highlight_roles")
+                    .description("Should mentions of one of your roles highlight")
+                    .default_value(true)
+                    .set_change_callback(move |_, option| {
+                        let inner = inner_clone
+                            .upgrade()
+                            .expect("Outer config has outlived inner config");
+                        inner.borrow_mut().look.highlight_roles = option.value();
+                    }),
+            )
+            .expect("Unable to create highlight roles option");
+
+            let inner_clone = Weak::clone(&inner);
+            look.new_boolean_option(
+                BooleanOptionSettings::new("highlight_everyone")
+                    .description("Should @everyone and @here highlight")
+                    .default_value(true)
+                    .set_change_callback(move |_, option| {
+                        let inner = inner_clone
+                            .upgrade()
+                            .expect("Outer config has outlived inner config");
+                        inner.borrow_mut().look.highlight_everyone = option.value();
+                    }),
+            )
+            .expect("Unable to create highlight everyone option");
+
+            let inner_clone = Weak::clone(&inner);
+            look.new_string_option(
+                StringOptionSettings::new("status_prefix")
+                    .description("Nicklist prefix showing the status of a member")
+                    .default_value("•")
+                    .set_change_callback(move |_, option| {
+                        let inner = inner_clone
+                            .upgrade()
+                            .expect("Outer config has outlived inner config");
+                        inner.borrow_mut().look.status_prefix = option.value().to_string();
+                    }),
+            )
+            .expect("Unable to create status prefix option");
+
+            let inner_clone = Weak::clone(&inner);
+            look.new_string_option(
+                StringOptionSettings::new("status_color_online")
+                    .description("Color of the status prefix of online members")
+                    .default_value("green")
+                    .set_change_callback(move |_, option| {
+                        let inner = inner_clone
+                            .upgrade()
+                            .expect("Outer config has outlived inner config");
+                        inner.borrow_mut().look.status_color_online = option.value().to_string();
+                    }),
+            )
+            .expect("Unable to create status color online option");
+
+            let inner_clone = Weak::clone(&inner);
+            look.new_string_option(
+                StringOptionSettings::new("status_color_idle")
+                    .description("Color of the status prefix of idle members")
+                    .default_value("yellow")
+                    .set_change_callback(move |_, option| {
+                        let inner = inner_clone
+                            .upgrade()
+                            .expect("Outer config has outlived inner config");
+                        inner.borrow_mut().look.status_color_idle = option.value().to_string();
+                    }),
+            )
+            .expect("Unable to create status color idle option");
+
+            let inner_clone = Weak::clone(&inner);
+            look.new_string_option(
+                StringOptionSettings::new("status_color_dnd")
+                    .description("Color of the status prefix of do not disturb members")
+                    .default_value("red")
+                    .set_change_callback(move |_, option| {
+                        let inner = inner_clone
+                            .upgrade()
+                            .expect("Outer config has outlived inner config");
+                        inner.borrow_mut().look.status_color_dnd = option.value().to_string();
+                    }),
+            )
+            .expect("Unable to create status color dnd option");
+
+            let inner_clone = Weak::clone(&inner);
+            look.new_string_option(
+                StringOptionSettings::new("status_color_offline")
+                    .description("Color of the status prefix of offline members")
+                    .default_value("darkgray")
+                    .set_change_callback(move |_, option| {
+                        let inner = inner_clone
+                            .upgrade()
+                            .expect("Outer config has outlived inner config");
+                        inner.borrow_mut().look.status_color_offline = option.value().to_string();
+                    }),
+            )
+            .expect("Unable to create status color offline option");
+
             let inner_clone = Weak::clone(&inner);
             look.new_string_option(
                 StringOptionSettings::new("readonly_value")
@@ -383,6 +750,61 @@ impl Config {
             )
             .expect("Unable to create readonly value option");
 
+            let inner_clone = Weak::clone(&inner);
+            look.new_string_option(
+                StringOptionSettings::new("emoji_style")
+                    .description(
+                        "How unicode emojis are rendered: unicode, shortcode (such as :thumbsup:) \
+                         or both",
+                    )
+                    .default_value("unicode")
+                    .set_check_callback(|_: &Weechat, _: &StringOption, value| {
+                        EmojiStyle::parse(&value).is_some()
+                    })
+                    .set_change_callback(move |_, option| {
+                        let inner = inner_clone
+                            .upgrade()
+                            .expect("Outer config has outlived inner config");
+                        inner.borrow_mut().look.emoji_style =
+                            EmojiStyle::parse(&option.value()).unwrap_or(EmojiStyle::Unicode);
+                    }),
+            )
+            .expect("Unable to create emoji style option");
+
+            let inner_clone = Weak::clone(&inner);
+            look.new_string_option(
+                StringOptionSettings::new("spoiler_char")
+                    .description("Character hidden spoilers are masked with")
+                    .default_value("█")
+                    .set_check_callback(|_: &Weechat, _: &StringOption, value| !value.is_empty())
+                    .set_change_callback(move |_, option| {
+                        let inner = inner_clone
+                            .upgrade()
+                            .expect("Outer config has outlived inner config");
+                        inner.borrow_mut().look.spoiler_char = option.value().to_string();
+                    }),
+            )
+            .expect("Unable to create spoiler char option");
+
+            let inner_clone = Weak::clone(&inner);
+            look.new_integer_option(
+                IntegerOptionSettings::new("redraw_interval")
+                    .description(
+                        "Minimum milliseconds between redraws of a buffer, changes from bursts of \
+                         events are drawn together",
+                    )
+                    .min(0)
+                    .max(10000)
+                    .default_value(100)
+                    .set_change_callback(move |_, option| {
+                        let inner = inner_clone
+                            .upgrade()
+                            .expect("Outer config has outlived inner config");
+                        inner.borrow_mut().look.redraw_interval = option.value();
+                    }),
+            )
+            .expect("Unable to create redraw interval option");
+
             let inner_clone = Weak::clone(&inner);
             look.new_integer_option(
                 IntegerOptionSettings::new("image_max_height")
@@ -424,6 +846,39 @@ impl Config {
             .expect("Unable to create image charset option");
         }
 
+        {
+            let inner = Rc::downgrade(&inner);
+            let color_section_options = ConfigSectionSettings::new("color");
+            let mut color = weechat_config
+                .new_section(color_section_options)
+                .expect("Unable to create color section");
+
+            let mut defaults = ColorConfig::default();
+            for (name, description, field) in color_options() {
+                let inner_clone = Weak::clone(&inner);
+                color
+                    .new_color_option(
+                        ColorOptionSettings::new(name)
+                            .description(description)
+                            .default_value(field(&mut defaults).as_str())
+                            .set_change_callback(move |_, option| {
+                                let inner = inner_clone
+                                    .upgrade()
+                                    .expect("Outer config has outlived inner config");
+                                let colors_changed = {
+                                    let mut inner = inner.borrow_mut();
+                                    *field(&mut inner.color) = option.value().to_string();
+                                    inner.colors_changed.clone()
+                                };
+                                if let Some(colors_changed) = colors_changed {
+                                    colors_changed();
+                                }
+                            }),
+                    )
+                    .expect("Unable to create color option");
+            }
+        }
+
         {
             let inner = Rc::downgrade(&inner);
             let server_section_options = ConfigSectionSettings::new("server")
@@ -516,6 +971,28 @@ impl Config {
         self.inner.borrow().look.show_unknown_user_ids
     }
 
+    pub fn highlight_roles(&self) -> bool {
+        self.inner.borrow().look.highlight_roles
+    }
+
+    pub fn highlight_everyone(&self) -> bool {
+        self.inner.borrow().look.highlight_everyone
+    }
+
+    pub fn status_prefix(&self) -> String {
+        self.inner.borrow().look.status_prefix.clone()
+    }
+
+    pub fn status_color(&self, status: Status) -> String {
+        let inner = self.inner.borrow();
+        match status {
+            Status::Online => inner.look.status_color_online.clone(),
+            Status::Idle => inner.look.status_color_idle.clone(),
+            Status::DoNotDisturb => inner.look.status_color_dnd.clone(),
+            Status::Invisible | Status::Offline => inner.look.status_color_offline.clone(),
+        }
+    }
+
     pub fn token(&self) -> Option<String> {
         self.inner
             .borrow()
@@ -540,10 +1017,77 @@ impl Config {
         self.inner.borrow().send_typing
     }
 
+    /// Whether typing status should be sent in a guild (or DMs), respecting the guild's override
+    pub fn send_typing_in(&self, guild_id: Option<Id<GuildMarker>>) -> bool {
+        let inner = self.inner.borrow();
+        guild_id
+            .and_then(|guild_id| inner.guilds.get(&guild_id))
+            .and_then(GuildConfig::send_typing)
+            .unwrap_or(inner.send_typing)
+    }
+
+    pub fn typing_delay(&self) -> i32 {
+        self.inner.borrow().typing_delay
+    }
+
+    pub fn typing_interval(&self) -> i32 {
+        self.inner.borrow().typing_interval
+    }
+
     pub fn join_all(&self) -> bool {
         self.inner.borrow().join_all
     }
 
+    pub fn reply_ping(&self) -> bool {
+        self.inner.borrow().reply_ping
+    }
+
+    pub fn status(&self) -> Status {
+        presence::parse_status(&self.inner.borrow().status).unwrap_or(Status::Online)
+    }
+
+    pub fn set_status(&self, status: Status) {
+        self.inner.borrow_mut().status = presence::status_name(status).to_owned();
+    }
+
+    /// The status to set while away, if away should be synced
+    pub fn sync_away(&self) -> Option<Status> {
+        match self.inner.borrow().sync_away.as_str() {
+            "off" => None,
+            status => presence::parse_status(status),
+        }
+    }
+
+    /// The configured custom status, if it is set and has not expired
+    pub fn custom_status(&self) -> Option<CustomStatus> {
+        let inner = self.inner.borrow();
+        if inner.custom_status.is_empty() && inner.custom_status_emoji.is_empty() {
+            return None;
+        }
+
+        Some(CustomStatus {
+            text: inner.custom_status.clone(),
+            emoji: Some(inner.custom_status_emoji.clone()).filter(|emoji| !emoji.is_empty()),
+            expires_at: inner.custom_status_expiry.parse().ok(),
+        })
+        .filter(|custom_status| !custom_status.is_expired())
+    }
+
+    pub fn set_custom_status(&self, custom_status: Option<CustomStatus>) {
+        let mut inner = self.inner.borrow_mut();
+        let custom_status = custom_status.unwrap_or(CustomStatus {
+            text: "".to_owned(),
+            emoji: None,
+            expires_at: None,
+        });
+        inner.custom_status = custom_status.text;
+        inner.custom_status_emoji = custom_status.emoji.unwrap_or_default();
+        inner.custom_status_expiry = custom_status
+            .expires_at
+            .map(|expires_at| expires_at.to_string())
+            .unwrap_or_default();
+    }
+
     pub fn nick_prefix(&self) -> String {
         self.inner.borrow().look.nick_prefix.clone()
     }
@@ -560,6 +1104,15 @@ impl Config {
         self.inner.borrow().color.nick_suffix_color.clone()
     }
 
+    pub fn colors(&self) -> ColorConfig {
+        self.inner.borrow().color.clone()
+    }
+
+    /// Set the function called after a color option changes
+    pub fn set_colors_changed_callback(&self, callback: impl Fn() + 'static) {
+        self.inner.borrow_mut().colors_changed = Some(Rc::new(callback));
+    }
+
     pub fn guilds(&self) -> HashMap<Id<GuildMarker>, GuildConfig> {
         self.inner.borrow().guilds.clone()
     }
@@ -584,6 +1137,18 @@ impl Config {
         self.inner.borrow().look.typing_list_style
     }
 
+    pub fn emoji_style(&self) -> EmojiStyle {
+        self.inner.borrow().look.emoji_style
+    }
+
+    pub fn spoiler_char(&self) -> String {
+        self.inner.borrow().look.spoiler_char.clone()
+    }
+
+    pub fn redraw_interval(&self) -> i32 {
+        self.inner.borrow().look.redraw_interval
+    }
+
     pub fn image_max_height(&self) -> i32 {
         self.inner.borrow().look.image_max_height
     }
@@ -631,11 +1196,62 @@ impl Config {
             .expect("send typing option must exist")
             .set(if self.send_typing() { "true" } else { "false" }, false);
 
+        general
+            .search_option("typing_delay")
+            .expect("typing delay option must exist")
+            .set(&self.typing_delay().to_string(), false);
+
+        general
+            .search_option("typing_interval")
+            .expect("typing interval option must exist")
+            .set(&self.typing_interval().to_string(), false);
+
         general
             .search_option("join_all")
             .expect("join all option must exist")
             .set(if self.join_all() { "true" } else { "false" }, false);
 
+        general
+            .search_option("reply_ping")
+            .expect("reply ping option must exist")
+            .set(if self.reply_ping() { "true" } else { "false" }, false);
+
+        let (status, custom_status, custom_status_emoji, custom_status_expiry, sync_away) = {
+            let inner = self.inner.borrow();
+            (
+                inner.status.clone(),
+                inner.custom_status.clone(),
+                inner.custom_status_emoji.clone(),
+                inner.custom_status_expiry.clone(),
+                inner.sync_away.clone(),
+            )
+        };
+
+        general
+            .search_option("status")
+            .expect("status option must exist")
+            .set(&status, false);
+
+        general
+            .search_option("custom_status")
+            .expect("custom status option must exist")
+            .set(&custom_status, false);
+
+        general
+            .search_option("custom_status_emoji")
+            .expect("custom status emoji option must exist")
+            .set(&custom_status_emoji, false);
+
+        general
+            .search_option("custom_status_expiry")
+            .expect("custom status expiry option must exist")
+            .set(&custom_status_expiry, false);
+
+        general
+            .search_option("sync_away")
+            .expect("sync away option must exist")
+            .set(&sync_away, false);
+
         let look = config
             .search_section("look")
             .expect("look option section must exist");
@@ -674,6 +1290,18 @@ impl Config {
                 false,
             );
 
+        look.search_option("emoji_style")
+            .expect("emoji style option must exist")
+            .set(self.emoji_style().name(), false);
+
+        look.search_option("spoiler_char")
+            .expect("spoiler char option must exist")
+            .set(&self.spoiler_char(), false);
+
+        look.search_option("redraw_interval")
+            .expect("redraw interval option must exist")
+            .set(&self.redraw_interval().to_string(), false);
+
         look.search_option("image_max_height")
             .expect("image max height option must exist")
             .set(&self.image_max_height().to_string(), false);
@@ -688,6 +1316,18 @@ impl Config {
         look.search_option("image_charset")
             .expect("image charset option must exist")
             .set(&charset_index.to_string(), false);
+
+        let color = config
+            .search_section("color")
+            .expect("color option section must exist");
+
+        let mut colors = self.colors();
+        for (name, _, field) in color_options() {
+            color
+                .search_option(name)
+                .expect("color option must exist")
+                .set(field(&mut colors), false);
+        }
     }
 }
 
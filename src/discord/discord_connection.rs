@@ -1,13 +1,18 @@
 use crate::{
     buffer::{channel::Channel, ext::BufferExt, guild::Guild},
     config::{Config, GuildConfig},
-    discord::{plugin_message::PluginMessage, typing_indicator::TypingEntry},
+    discord::{
+        notification_settings::{self, NotificationSettings},
+        plugin_message::PluginMessage,
+        typing_indicator::{TypingEntry, TYPING_TIMEOUT},
+    },
     instance::Instance,
     refcell::{Ref, RefCell},
-    twilight_utils::ext::{MemberExt, UserExt},
+    twilight_utils::ext::{ChannelExt, MemberExt, UserExt},
 };
 use anyhow::Result;
 use futures::StreamExt;
+use parking_lot::RwLock;
 use std::{collections::HashMap, sync::Arc, time::Duration};
 use tokio::{
     runtime::Runtime,
@@ -20,13 +25,20 @@ use tokio::{
 use twilight_cache_inmemory::InMemoryCache;
 use twilight_gateway::{
     shard::{ShardBuilder, ShardStartErrorType},
-    Event as GatewayEvent, Intents, Shard,
+    Event as GatewayEvent, EventTypeFlags, Intents, Shard,
 };
 use twilight_http::{error::ErrorType as HttpErrorType, Client as HttpClient};
 use twilight_model::{
     channel::Channel as TwilightChannel,
+    gateway::{
+        payload::{
+            incoming::MemberListItem,
+            outgoing::{update_presence::UpdatePresencePayload, UpdatePresence},
+        },
+        OpCode,
+    },
     id::{
-        marker::{ChannelMarker, GuildMarker},
+        marker::{ChannelMarker, GuildMarker, UserMarker},
         Id,
     },
 };
@@ -38,6 +50,7 @@ pub struct ConnectionInner {
     pub rt: Arc<Runtime>,
     pub cache: Arc<InMemoryCache>,
     pub http: Arc<HttpClient>,
+    pub notification_settings: Arc<RwLock<NotificationSettings>>,
     /// All channels we have requested events for
     subscriptions: Arc<TokioMutex<HashMap<Id<GuildMarker>, Vec<Id<ChannelMarker>>>>>,
 }
@@ -54,7 +67,12 @@ impl DiscordConnection {
         self.0.borrow()
     }
 
-    pub async fn start(&self, token: &str, tx: Sender<PluginMessage>) -> Result<ConnectionInner> {
+    pub async fn start(
+        &self,
+        token: &str,
+        presence: UpdatePresencePayload,
+        tx: Sender<PluginMessage>,
+    ) -> Result<ConnectionInner> {
         let (cache_tx, cache_rx) = channel();
         let runtime = Arc::new(Runtime::new().expect("Unable to create tokio runtime"));
         let token = token.to_owned();
@@ -63,7 +81,10 @@ impl DiscordConnection {
             let rt = runtime.clone();
             runtime.spawn(async move {
                 let http = Arc::new(HttpClient::new(token.to_owned()));
+                // Raw payloads are needed for the notification settings, which twilight ignores
                 let (shard, mut events) = ShardBuilder::new(token, Intents::all())
+                    .event_types(EventTypeFlags::default() | EventTypeFlags::SHARD_PAYLOAD)
+                    .presence(presence)
                     .http_client(http.clone())
                     .build();
                 let shard = Arc::new(shard);
@@ -162,6 +183,7 @@ impl DiscordConnection {
             rt: runtime,
             cache,
             http,
+            notification_settings: Arc::new(RwLock::new(NotificationSettings::default())),
             subscriptions: Arc::new(TokioMutex::new(HashMap::new())),
         };
 
@@ -176,6 +198,27 @@ impl DiscordConnection {
         }
     }
 
+    /// Replace our presence, such as the status and custom status
+    pub async fn update_presence(&self, presence: UpdatePresencePayload) -> Result<()> {
+        let inner = match self.0.borrow().as_ref().cloned() {
+            Some(inner) => inner,
+            None => anyhow::bail!("not connected"),
+        };
+
+        let shard = inner.shard.clone();
+        inner
+            .rt
+            .spawn(async move {
+                let command = UpdatePresence {
+                    d: presence,
+                    op: OpCode::PresenceUpdate,
+                };
+                Ok(shard.command(&command).await?)
+            })
+            .await
+            .expect("Task is never aborted")
+    }
+
     /// Add provided channel to the event subscription list (opcode 14, lazy guilds)
     pub async fn send_guild_subscription(
         &self,
@@ -240,11 +283,18 @@ impl DiscordConnection {
         instance: Instance,
     ) {
         loop {
-            let event = match rx.recv().await {
-                Some(e) => e,
-                None => {
-                    Weechat::print("discord: error receiving message");
-                    return;
+            let event = match rx.try_recv() {
+                Ok(e) => e,
+                Err(_) => {
+                    // The queue has drained, so the redraws of a burst of events can be flushed
+                    crate::weecord_renderer::flush_pending_redraws();
+                    match rx.recv().await {
+                        Some(e) => e,
+                        None => {
+                            Weechat::print("discord: error receiving message");
+                            return;
+                        },
+                    }
                 },
             };
 
@@ -253,6 +303,29 @@ impl DiscordConnection {
                     Weechat::print(&format!("discord: ready as: {}", user.tag()));
                     tracing::info!("Ready as {}", user.tag());
 
+                    // The shard identifies with the presence it was started with, which may have
+                    // changed since if this is a reconnect
+                    let shard = conn.shard.clone();
+                    let presence = match (config.sync_away(), instance.away_message()) {
+                        (Some(away_status), Some(message)) => {
+                            super::presence::away_presence(away_status, message)
+                        },
+                        _ => super::presence::presence(
+                            config.status(),
+                            config.custom_status().as_ref(),
+                        ),
+                    };
+                    conn.rt.spawn(async move {
+                        let command = UpdatePresence {
+                            d: presence,
+                            op: OpCode::PresenceUpdate,
+                        };
+                        if let Err(e) = shard.command(&command).await {
+                            tracing::warn!("Unable to restore presence: {}", e);
+                        }
+                    });
+                    Weechat::bar_item_update("discord_status");
+
                     let guilds: Vec<_> = if config.join_all() {
                         conn.cache
                             .iter()
@@ -349,70 +422,31 @@ impl DiscordConnection {
                         }
                     }
 
-                    let channel = if let Some(guild_id) = message.guild_id {
-                        let channels = match instance.borrow_guilds().get(&guild_id) {
-                            Some(guild) => guild.channels(),
-                            None => continue,
-                        };
-
-                        match channels.get(&message.channel_id) {
-                            Some(channel) => channel.clone(),
-                            None => continue,
+                    instance.dispatch(message.channel_id, |route| {
+                        if let Some(channel) = &route.channel {
+                            channel.add_message(&message.into());
                         }
-                    } else {
-                        let private_channels = instance.borrow_private_channels_mut();
-                        match private_channels.get(&message.channel_id) {
-                            Some(channel) => channel.clone(),
-                            None => continue,
-                        }
-                    };
-                    channel.add_message(&message.into());
+                    });
                 },
                 PluginMessage::MessageDelete { event } => {
-                    if let Some(guild_id) = event.guild_id {
-                        let channels = match instance.borrow_guilds().get(&guild_id) {
-                            Some(guild) => guild.channels(),
-                            None => continue,
-                        };
-
-                        let channel = match channels.get(&event.channel_id) {
-                            Some(channel) => channel,
-                            None => continue,
-                        };
-
-                        channel.remove_message(event.id);
-                    } else {
-                        let private_channels = instance.borrow_private_channels_mut();
-                        let channel = match private_channels.get(&event.channel_id) {
-                            Some(channel) => channel,
-                            None => continue,
-                        };
-
-                        channel.remove_message(event.id);
-                    }
+                    instance.dispatch(event.channel_id, |route| {
+                        if let Some(channel) = &route.channel {
+                            channel.remove_message(event.id);
+                        }
+                        if let Some(pins) = &route.pins {
+                            pins.remove_message(event.id);
+                        }
+                    });
                 },
                 PluginMessage::MessageUpdate { message } => {
-                    if let Some(guild_id) = message.guild_id {
-                        let channels = match instance.borrow_guilds().get(&guild_id) {
-                            Some(guild) => guild.channels(),
-                            None => continue,
-                        };
-
-                        let channel = match channels.get(&message.channel_id) {
-                            Some(channel) => channel,
-                            None => continue,
-                        };
-
-                        channel.update_message(*message);
-                    } else {
-                        let private_channels = instance.borrow_private_channels_mut();
-                        let channel = match private_channels.get(&message.channel_id) {
-                            Some(channel) => channel,
-                            None => continue,
-                        };
-
-                        channel.update_message(*message);
-                    }
+                    instance.dispatch(message.channel_id, |route| {
+                        if let Some(pins) = &route.pins {
+                            pins.update_message((*message).clone());
+                        }
+                        if let Some(channel) = &route.channel {
+                            channel.update_message(*message);
+                        }
+                    });
                 },
                 PluginMessage::MemberChunk(member_chunk) => {
                     let channel_id = member_chunk
@@ -425,16 +459,11 @@ impl DiscordConnection {
                         );
                     }
                     if let Some(channel_id) = channel_id {
-                        let channels = match instance.borrow_guilds().get(&member_chunk.guild_id) {
-                            Some(guild) => guild.channels(),
-                            None => continue,
-                        };
-
-                        let channel = match channels.get(&channel_id) {
-                            Some(channel) => channel,
-                            None => continue,
-                        };
-                        channel.redraw(&member_chunk.not_found);
+                        instance.dispatch(channel_id, |route| {
+                            if let Some(channel) = &route.channel {
+                                channel.schedule_redraw(&member_chunk.not_found);
+                            }
+                        });
                     }
                 },
                 PluginMessage::TypingStart(typing) => {
@@ -457,24 +486,10 @@ impl DiscordConnection {
                             guild_id: typing.guild_id,
                             user: typing.user_id,
                             user_name: name,
-                            time: typing.timestamp,
+                            deadline: typing.timestamp + TYPING_TIMEOUT,
                         });
+                        // Expired entries are swept by the typing bar item's timer
                         Weechat::bar_item_update("discord_typing");
-                        conn.rt
-                            .spawn(async move {
-                                tokio::time::sleep(Duration::from_secs(10)).await;
-                            })
-                            .await
-                            .expect("Task is never aborted");
-
-                        Weechat::spawn({
-                            let instance = instance.clone();
-                            async move {
-                                instance.borrow_typing_tracker_mut().sweep();
-                                Weechat::bar_item_update("discord_typing");
-                            }
-                        })
-                        .detach();
                     }
                 },
                 PluginMessage::ChannelUpdate(channel_update) => {
@@ -484,87 +499,126 @@ impl DiscordConnection {
                         Weechat::bar_item_update("discord_slowmode_cooldown");
                     }
                 },
+                PluginMessage::MessageAck(ack) => {
+                    let guild_id = conn
+                        .cache
+                        .channel(ack.channel_id)
+                        .and_then(|channel| channel.guild_id);
+                    if let Some(channel) = instance.search_buffer(guild_id, ack.channel_id) {
+                        channel.mark_read(ack.message_id);
+                    }
+                },
+                PluginMessage::GuildSettings(settings) => {
+                    conn.notification_settings.write().update(settings);
+                },
+                PluginMessage::ThreadCreate(thread_create) => {
+                    let thread = thread_create.0;
+                    let (guild_id, parent_id) = match (thread.guild_id, thread.parent_id) {
+                        (Some(guild_id), Some(parent_id)) => (guild_id, parent_id),
+                        _ => continue,
+                    };
+                    if let Some(parent) = instance.search_buffer(Some(guild_id), parent_id) {
+                        let name = thread.name();
+                        parent.print(&format!(
+                            "discord: thread \"{}\" started, use /discord thread join {} to open \
+                             it",
+                            name,
+                            crate::utils::clean_name(&name)
+                        ));
+                    }
+                },
+                PluginMessage::ThreadUpdate(thread_update) => {
+                    let thread = thread_update.0;
+                    if let Some(channel) = instance.search_buffer(thread.guild_id, thread.id) {
+                        channel.update_thread(&thread, &conn.cache);
+                    }
+                },
+                PluginMessage::ThreadDelete(thread_delete) => {
+                    if instance
+                        .search_buffer(Some(thread_delete.guild_id), thread_delete.id)
+                        .is_some()
+                    {
+                        if let Some(channel) = instance.remove_channel(thread_delete.id) {
+                            channel.close();
+                            channel.set_closed();
+                        }
+                        if let Some(parent) = instance
+                            .search_buffer(Some(thread_delete.guild_id), thread_delete.parent_id)
+                        {
+                            parent.print("discord: a thread you joined has been deleted");
+                        }
+                    }
+                },
                 PluginMessage::ReactionAdd(reaction_add) => {
                     let reaction = reaction_add.0;
-                    if let Some(guild_id) = reaction.guild_id {
-                        let channels = match instance.borrow_guilds().get(&guild_id) {
-                            Some(guild) => guild.channels(),
-                            None => continue,
-                        };
-
-                        let channel = match channels.get(&reaction.channel_id) {
-                            Some(channel) => channel,
-                            None => continue,
-                        };
-
-                        channel.add_reaction(&conn.cache, &reaction);
-                    } else {
-                        let private_channels = instance.borrow_private_channels_mut();
-                        let channel = match private_channels.get(&reaction.channel_id) {
-                            Some(channel) => channel,
-                            None => continue,
-                        };
-
-                        channel.add_reaction(&conn.cache, &reaction);
-                    }
+                    instance.dispatch(reaction.channel_id, |route| {
+                        if let Some(channel) = &route.channel {
+                            channel.add_reaction(&conn.cache, &reaction);
+                        }
+                    });
                 },
                 PluginMessage::ReactionRemove(reaction_remove) => {
                     let reaction = reaction_remove.0;
-                    match reaction.guild_id {
-                        Some(guild_id) => {
-                            let channels = match instance.borrow_guilds().get(&guild_id) {
-                                Some(guild) => guild.channels(),
-                                None => continue,
-                            };
-
-                            let channel = match channels.get(&reaction.channel_id) {
-                                Some(channel) => channel,
-                                None => continue,
-                            };
-
+                    instance.dispatch(reaction.channel_id, |route| {
+                        if let Some(channel) = &route.channel {
                             channel.remove_reaction(&reaction);
-                        },
-                        None => {
-                            let private_channels = instance.borrow_private_channels_mut();
-                            let channel = match private_channels.get(&reaction.channel_id) {
-                                Some(channel) => channel,
-                                None => continue,
-                            };
-
-                            channel.remove_reaction(&reaction);
-                        },
-                    }
+                        }
+                    });
                 },
                 PluginMessage::MemberListUpdate(update) => {
-                    let mut member_lists = instance.borrow_member_lists_mut();
-                    let member_list = member_lists.entry(update.guild_id).or_default();
                     let guild_id = update.guild_id;
+                    instance
+                        .borrow_member_lists_mut()
+                        .entry(guild_id)
+                        .or_default()
+                        .apply_update(*update);
 
-                    member_list.apply_update(*update);
+                    Self::update_current_nicklist(conn, &instance, guild_id, None);
+                },
+                PluginMessage::PresenceUpdate { guild_id, user_id } => {
+                    // The presence has already been updated by the cache
+                    Self::update_current_nicklist(conn, &instance, guild_id, Some(user_id));
+                    Weechat::bar_item_update("discord_nick_activity");
+                },
+            }
+        }
+    }
 
-                    let channel_id =
-                        match unsafe { Weechat::weechat() }.current_buffer().channel_id() {
-                            Some(channel_id) => channel_id,
-                            None => continue,
-                        };
+    /// Redraw the nicklist of the current buffer if it belongs to the guild, and lists the member
+    /// if one is given
+    fn update_current_nicklist(
+        conn: &ConnectionInner,
+        instance: &Instance,
+        guild_id: Id<GuildMarker>,
+        user_id: Option<Id<UserMarker>>,
+    ) {
+        let channel_id = match unsafe { Weechat::weechat() }.current_buffer().channel_id() {
+            Some(channel_id) => channel_id,
+            None => return,
+        };
 
-                    let channels = match instance.borrow_guilds().get(&guild_id) {
-                        Some(guild) => guild.channels(),
-                        None => continue,
-                    };
+        let channel = match instance.search_buffer(Some(guild_id), channel_id) {
+            Some(channel) => channel,
+            None => return,
+        };
 
-                    let channel = match channels.get(&channel_id) {
-                        Some(channel) => channel,
-                        None => continue,
-                    };
+        let member_lists = instance.borrow_member_lists();
+        let channel_memberlist = match member_lists
+            .get(&guild_id)
+            .and_then(|member_list| member_list.get_list_for_channel(channel_id, &conn.cache))
+        {
+            Some(channel_memberlist) => channel_memberlist,
+            None => return,
+        };
 
-                    if let Some(channel_memberlist) =
-                        member_list.get_list_for_channel(channel_id, &conn.cache)
-                    {
-                        channel.update_nicklist(channel_memberlist);
-                    }
-                },
-            }
+        let listed = user_id.map_or(true, |user_id| {
+            channel_memberlist.iter().any(|item| match item {
+                MemberListItem::Member(member) => member.user.id == user_id,
+                MemberListItem::Group(_) => false,
+            })
+        });
+        if listed {
+            channel.update_nicklist(channel_memberlist);
         }
     }
 
@@ -584,9 +638,7 @@ impl DiscordConnection {
             }
         }
 
-        instance
-            .borrow_private_channels_mut()
-            .insert(channel_id, channel.clone());
+        let _old = instance.insert_private_channel(channel_id, channel.clone());
 
         Ok(channel)
     }
@@ -627,6 +679,8 @@ impl DiscordConnection {
             GatewayEvent::MessageUpdate(message) => {
                 tx.send(PluginMessage::MessageUpdate { message }).await
             },
+            // The cached read state has already been updated by the cache
+            GatewayEvent::MessageAck(ack) => tx.send(PluginMessage::MessageAck(ack)).await,
             GatewayEvent::MemberChunk(member_chunk) => {
                 tx.send(PluginMessage::MemberChunk(member_chunk)).await
             },
@@ -636,16 +690,41 @@ impl DiscordConnection {
             GatewayEvent::ChannelUpdate(channel_update) => {
                 tx.send(PluginMessage::ChannelUpdate(channel_update)).await
             },
+            GatewayEvent::ThreadCreate(thread_create) => {
+                tx.send(PluginMessage::ThreadCreate(thread_create)).await
+            },
+            GatewayEvent::ThreadUpdate(thread_update) => {
+                tx.send(PluginMessage::ThreadUpdate(thread_update)).await
+            },
+            GatewayEvent::ThreadDelete(thread_delete) => {
+                tx.send(PluginMessage::ThreadDelete(thread_delete)).await
+            },
             GatewayEvent::ReactionAdd(reaction_add) => {
                 tx.send(PluginMessage::ReactionAdd(reaction_add)).await
             },
             GatewayEvent::MemberListUpdate(update) => {
                 tx.send(PluginMessage::MemberListUpdate(update)).await
             },
+            GatewayEvent::PresenceUpdate(update) => match update.0.guild_id {
+                Some(guild_id) => {
+                    tx.send(PluginMessage::PresenceUpdate {
+                        guild_id,
+                        user_id: update.0.user.id(),
+                    })
+                    .await
+                },
+                None => Ok(()),
+            },
             GatewayEvent::ReactionRemove(reaction_remove) => {
                 tx.send(PluginMessage::ReactionRemove(reaction_remove))
                     .await
             },
+            GatewayEvent::ShardPayload(payload) => {
+                match notification_settings::settings_from_payload(&payload.bytes) {
+                    Some(settings) => tx.send(PluginMessage::GuildSettings(settings)).await,
+                    None => Ok(()),
+                }
+            },
             _ => Ok(()),
         }
     }
@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+use twilight_model::id::{
+    marker::{ChannelMarker, GenericMarker, MessageMarker, UserMarker},
+    Id,
+};
+
+// Forum fields are not modeled by twilight yet, so the relevant parts of the payloads are
+// deserialized here
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ForumTag {
+    pub id: Id<GenericMarker>,
+    pub name: String,
+    pub emoji_name: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ForumChannel {
+    #[serde(default)]
+    pub available_tags: Vec<ForumTag>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ForumPost {
+    pub id: Id<ChannelMarker>,
+    pub parent_id: Option<Id<ChannelMarker>>,
+    pub name: String,
+    pub owner_id: Option<Id<UserMarker>>,
+    pub message_count: Option<u64>,
+    pub last_message_id: Option<Id<MessageMarker>>,
+    #[serde(default)]
+    pub applied_tags: Vec<Id<GenericMarker>>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ThreadsListing {
+    pub threads: Vec<ForumPost>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CreateForumPost<'a> {
+    pub name: &'a str,
+    pub message: CreateForumPostMessage<'a>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CreateForumPostMessage<'a> {
+    pub content: &'a str,
+}
@@ -1,4 +1,7 @@
 mod custom_commands;
 pub mod discord_connection;
+pub mod forum;
+pub mod notification_settings;
 pub mod plugin_message;
+pub mod presence;
 pub mod typing_indicator;
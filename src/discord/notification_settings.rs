@@ -0,0 +1,219 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{borrow::Cow, collections::HashMap};
+use twilight_cache_inmemory::InMemoryCache;
+use twilight_model::{
+    guild::DefaultMessageNotificationLevel,
+    id::{
+        marker::{ChannelMarker, GuildMarker},
+        Id,
+    },
+};
+
+// The user's notification settings are only sent to user accounts, so twilight does not model them
+// and they are parsed from the raw gateway payloads
+
+/// `message_notifications` value meaning the setting is inherited
+const INHERIT: u8 = 3;
+
+fn inherit() -> u8 {
+    INHERIT
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationLevel {
+    All,
+    Mentions,
+    Nothing,
+}
+
+impl NotificationLevel {
+    fn from_discord(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::All),
+            1 => Some(Self::Mentions),
+            2 => Some(Self::Nothing),
+            _ => None,
+        }
+    }
+}
+
+/// How long a temporary mute lasts
+#[derive(Deserialize, Debug, Clone)]
+pub struct MuteConfig {
+    /// An ISO 8601 timestamp, `None` if the mute lasts until it is removed
+    pub end_time: Option<String>,
+}
+
+/// Whether a mute is in effect, temporary mutes stop applying once they expire
+fn is_muted(muted: bool, mute_config: &Option<MuteConfig>) -> bool {
+    muted
+        && mute_config
+            .as_ref()
+            .and_then(|config| config.end_time.as_deref())
+            .and_then(|end_time| DateTime::parse_from_rfc3339(end_time).ok())
+            .map_or(true, |end_time| end_time > Utc::now())
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ChannelOverride {
+    pub channel_id: Id<ChannelMarker>,
+    #[serde(default)]
+    pub muted: bool,
+    #[serde(default)]
+    pub mute_config: Option<MuteConfig>,
+    #[serde(default = "inherit")]
+    pub message_notifications: u8,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct UserGuildSettings {
+    /// `None` for direct messages
+    pub guild_id: Option<Id<GuildMarker>>,
+    #[serde(default)]
+    pub muted: bool,
+    #[serde(default)]
+    pub mute_config: Option<MuteConfig>,
+    #[serde(default = "inherit")]
+    pub message_notifications: u8,
+    #[serde(default)]
+    pub suppress_everyone: bool,
+    #[serde(default)]
+    pub suppress_roles: bool,
+    #[serde(default)]
+    pub channel_overrides: Vec<ChannelOverride>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum UserGuildSettingsList {
+    Entries { entries: Vec<UserGuildSettings> },
+    List(Vec<UserGuildSettings>),
+}
+
+/// The effective notification settings of a channel
+#[derive(Clone, Copy, Debug)]
+pub struct ChannelNotifications {
+    pub level: NotificationLevel,
+    pub muted: bool,
+    pub suppress_everyone: bool,
+    pub suppress_roles: bool,
+}
+
+#[derive(Default, Debug)]
+pub struct NotificationSettings(HashMap<Option<Id<GuildMarker>>, UserGuildSettings>);
+
+impl NotificationSettings {
+    pub fn update(&mut self, settings: Vec<UserGuildSettings>) {
+        for guild_settings in settings {
+            self.0.insert(guild_settings.guild_id, guild_settings);
+        }
+    }
+
+    /// Resolve the settings of a channel, a local override takes precedence over everything else
+    pub fn resolve(
+        &self,
+        cache: &InMemoryCache,
+        guild_id: Option<Id<GuildMarker>>,
+        channel_id: Id<ChannelMarker>,
+        local_override: Option<NotificationLevel>,
+    ) -> ChannelNotifications {
+        let guild_settings = self.0.get(&guild_id);
+        let suppress_everyone = guild_settings.map_or(false, |s| s.suppress_everyone);
+        let suppress_roles = guild_settings.map_or(false, |s| s.suppress_roles);
+
+        if let Some(level) = local_override {
+            return ChannelNotifications {
+                level,
+                muted: false,
+                suppress_everyone,
+                suppress_roles,
+            };
+        }
+
+        // Channels inherit the settings of their category, and threads those of their channel
+        let parent_id = cache.channel(channel_id).and_then(|c| c.parent_id);
+        let channel_override = |id: Id<ChannelMarker>| {
+            guild_settings.and_then(|s| s.channel_overrides.iter().find(|o| o.channel_id == id))
+        };
+        let overrides = [Some(channel_id), parent_id]
+            .iter()
+            .flatten()
+            .filter_map(|id| channel_override(*id))
+            .collect::<Vec<_>>();
+
+        let muted = guild_settings.map_or(false, |s| is_muted(s.muted, &s.mute_config))
+            || overrides.iter().any(|o| is_muted(o.muted, &o.mute_config));
+        let level = overrides
+            .iter()
+            .map(|o| o.message_notifications)
+            .chain(guild_settings.map(|s| s.message_notifications))
+            .find_map(NotificationLevel::from_discord)
+            .unwrap_or_else(|| {
+                match guild_id
+                    .and_then(|guild_id| cache.guild(guild_id))
+                    .map(|guild| guild.default_message_notifications())
+                {
+                    Some(DefaultMessageNotificationLevel::OnlyMentions) => {
+                        NotificationLevel::Mentions
+                    },
+                    _ => NotificationLevel::All,
+                }
+            });
+
+        ChannelNotifications {
+            level,
+            muted,
+            suppress_everyone,
+            suppress_roles,
+        }
+    }
+}
+
+/// Extract notification settings from a raw gateway payload, if it contains any
+pub fn settings_from_payload(bytes: &[u8]) -> Option<Vec<UserGuildSettings>> {
+    #[derive(Deserialize)]
+    struct Dispatch<'a> {
+        #[serde(borrow)]
+        t: Option<Cow<'a, str>>,
+    }
+    #[derive(Deserialize)]
+    struct Payload<T> {
+        d: T,
+    }
+    #[derive(Deserialize)]
+    struct Ready {
+        user_guild_settings: UserGuildSettingsList,
+    }
+
+    // Every gateway payload passes through here, so skip the others without parsing them,
+    // discord sends the event name first so only the start of the payload is searched
+    const EVENT_NAME: &[u8] = br#""t":""#;
+    let head = &bytes[..bytes.len().min(64)];
+    let event_name = head
+        .windows(EVENT_NAME.len())
+        .position(|window| window == EVENT_NAME)
+        .map(|pos| &bytes[pos + EVENT_NAME.len()..])?;
+    if !event_name.starts_with(br#"READY""#)
+        && !event_name.starts_with(br#"USER_GUILD_SETTINGS_UPDATE""#)
+    {
+        return None;
+    }
+
+    let dispatch: Dispatch = serde_json::from_slice(bytes).ok()?;
+    match dispatch.t.as_deref()? {
+        "READY" => {
+            let payload: Payload<Ready> = serde_json::from_slice(bytes).ok()?;
+            Some(match payload.d.user_guild_settings {
+                UserGuildSettingsList::Entries { entries } => entries,
+                UserGuildSettingsList::List(list) => list,
+            })
+        },
+        "USER_GUILD_SETTINGS_UPDATE" => {
+            let payload: Payload<UserGuildSettings> = serde_json::from_slice(bytes).ok()?;
+            Some(vec![payload.d])
+        },
+        _ => None,
+    }
+}
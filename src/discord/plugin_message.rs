@@ -1,21 +1,43 @@
+use crate::discord::notification_settings::UserGuildSettings;
 use twilight_model::{
     channel::Message,
     gateway::payload::incoming::{
-        ChannelUpdate, MemberChunk, MemberListUpdate, MessageDelete, MessageUpdate, ReactionAdd,
-        ReactionRemove, TypingStart,
+        ChannelUpdate, MemberChunk, MemberListUpdate, MessageAck, MessageDelete, MessageUpdate,
+        ReactionAdd, ReactionRemove, ThreadCreate, ThreadDelete, ThreadUpdate, TypingStart,
+    },
+    id::{
+        marker::{GuildMarker, UserMarker},
+        Id,
     },
     user::CurrentUser,
 };
 
 pub enum PluginMessage {
-    Ready { user: CurrentUser },
-    MessageCreate { message: Box<Message> },
-    MessageDelete { event: MessageDelete },
-    MessageUpdate { message: Box<MessageUpdate> },
+    Ready {
+        user: CurrentUser,
+    },
+    MessageCreate {
+        message: Box<Message>,
+    },
+    MessageDelete {
+        event: MessageDelete,
+    },
+    MessageUpdate {
+        message: Box<MessageUpdate>,
+    },
+    MessageAck(MessageAck),
+    GuildSettings(Vec<UserGuildSettings>),
     MemberChunk(MemberChunk),
     TypingStart(TypingStart),
     ChannelUpdate(Box<ChannelUpdate>),
+    ThreadCreate(Box<ThreadCreate>),
+    ThreadUpdate(Box<ThreadUpdate>),
+    ThreadDelete(ThreadDelete),
     ReactionAdd(Box<ReactionAdd>),
     MemberListUpdate(Box<MemberListUpdate>),
+    PresenceUpdate {
+        guild_id: Id<GuildMarker>,
+        user_id: Id<UserMarker>,
+    },
     ReactionRemove(Box<ReactionRemove>),
 }
@@ -0,0 +1,167 @@
+use std::{fmt, time::Duration};
+use time::OffsetDateTime;
+use twilight_cache_inmemory::InMemoryCache;
+use twilight_model::{
+    gateway::{
+        payload::outgoing::update_presence::UpdatePresencePayload,
+        presence::{Activity, ActivityEmoji, ActivityType, Status},
+    },
+    id::{
+        marker::{GuildMarker, UserMarker},
+        Id,
+    },
+};
+
+/// A custom status, shown under the user's name
+#[derive(Clone, Debug, PartialEq)]
+pub struct CustomStatus {
+    pub text: String,
+    pub emoji: Option<String>,
+    /// Unix timestamp after which the status is cleared
+    pub expires_at: Option<i64>,
+}
+
+impl CustomStatus {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.map_or(false, |expires_at| {
+            expires_at <= OffsetDateTime::now_utc().unix_timestamp()
+        })
+    }
+
+    fn activity(&self) -> Activity {
+        Activity {
+            application_id: None,
+            assets: None,
+            buttons: Vec::new(),
+            created_at: None,
+            details: None,
+            emoji: self.emoji.clone().map(|name| ActivityEmoji {
+                animated: None,
+                name,
+                id: None,
+            }),
+            flags: None,
+            id: None,
+            instance: None,
+            kind: ActivityType::Custom,
+            name: "Custom Status".to_owned(),
+            party: None,
+            secrets: None,
+            state: Some(self.text.clone()).filter(|text| !text.is_empty()),
+            timestamps: None,
+            url: None,
+        }
+    }
+}
+
+impl fmt::Display for CustomStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.emoji {
+            Some(emoji) if self.text.is_empty() => write!(f, "{}", emoji),
+            Some(emoji) => write!(f, "{} {}", emoji, self.text),
+            None => write!(f, "{}", self.text),
+        }
+    }
+}
+
+pub fn parse_status(status: &str) -> Option<Status> {
+    match status {
+        "online" => Some(Status::Online),
+        "idle" => Some(Status::Idle),
+        "dnd" => Some(Status::DoNotDisturb),
+        "invisible" => Some(Status::Invisible),
+        _ => None,
+    }
+}
+
+pub fn status_name(status: Status) -> &'static str {
+    match status {
+        Status::Online => "online",
+        Status::Idle => "idle",
+        Status::DoNotDisturb => "dnd",
+        Status::Invisible => "invisible",
+        Status::Offline => "offline",
+    }
+}
+
+/// Describe an activity the way the client does, such as "Playing X" or a custom status
+pub fn format_activity(activity: &Activity) -> String {
+    match activity.kind {
+        ActivityType::Custom => {
+            let emoji = activity.emoji.as_ref().map(|emoji| emoji.name.as_str());
+            match (emoji, activity.state.as_deref()) {
+                (Some(emoji), Some(state)) => format!("{} {}", emoji, state),
+                (Some(emoji), None) => emoji.to_owned(),
+                (None, Some(state)) => state.to_owned(),
+                (None, None) => "".to_owned(),
+            }
+        },
+        ActivityType::Playing => format!("Playing {}", activity.name),
+        ActivityType::Streaming => format!("Streaming {}", activity.name),
+        ActivityType::Listening => format!("Listening to {}", activity.name),
+        ActivityType::Watching => format!("Watching {}", activity.name),
+        ActivityType::Competing => format!("Competing in {}", activity.name),
+        _ => activity.name.clone(),
+    }
+}
+
+/// Describe the status and activities of a guild member, such as "idle, Playing X"
+pub fn describe_member_presence(
+    cache: &InMemoryCache,
+    guild_id: Id<GuildMarker>,
+    user_id: Id<UserMarker>,
+) -> String {
+    match cache.presence(guild_id, user_id) {
+        Some(presence) => std::iter::once(status_name(presence.status()).to_owned())
+            .chain(
+                presence
+                    .activities()
+                    .iter()
+                    .map(format_activity)
+                    .filter(|activity| !activity.is_empty()),
+            )
+            .collect::<Vec<_>>()
+            .join(", "),
+        None => status_name(Status::Offline).to_owned(),
+    }
+}
+
+/// Parse a status duration such as `30m`, `4h` or `1d`
+pub fn parse_expiry(expiry: &str) -> Option<Duration> {
+    let unit_index = expiry.find(|c: char| !c.is_ascii_digit())?;
+    let (count, unit) = expiry.split_at(unit_index);
+    let count: u64 = count.parse().ok()?;
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return None,
+    };
+    Some(Duration::from_secs(count.checked_mul(unit_secs)?))
+}
+
+/// Build the presence to send, an expired custom status is omitted
+pub fn presence(status: Status, custom_status: Option<&CustomStatus>) -> UpdatePresencePayload {
+    // Built directly as the constructor rejects presences without an activity
+    UpdatePresencePayload {
+        activities: custom_status
+            .filter(|custom_status| !custom_status.is_expired())
+            .map(CustomStatus::activity)
+            .into_iter()
+            .collect(),
+        afk: false,
+        since: None,
+        status,
+    }
+}
+
+/// Build the presence to send while weechat is away, with the away message as the custom status
+pub fn away_presence(status: Status, message: String) -> UpdatePresencePayload {
+    let custom_status = CustomStatus {
+        text: message,
+        emoji: None,
+        expires_at: None,
+    };
+    presence(status, Some(&custom_status))
+}
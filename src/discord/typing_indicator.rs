@@ -10,6 +10,8 @@ use twilight_model::id::{
 };
 
 const MAX_TYPING_EVENTS: usize = 50;
+/// Seconds a user is shown as typing after their last typing event
+pub const TYPING_TIMEOUT: u64 = 10;
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct TypingEntry {
@@ -17,18 +19,19 @@ pub struct TypingEntry {
     pub guild_id: Option<Id<GuildMarker>>,
     pub user: Id<UserMarker>,
     pub user_name: String,
-    pub time: u64,
+    /// Unix timestamp, in seconds, at which the entry expires
+    pub deadline: u64,
 }
 
 impl PartialOrd for TypingEntry {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.time.partial_cmp(&other.time)
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for TypingEntry {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.time.cmp(&other.time)
+        self.deadline.cmp(&other.deadline)
     }
 }
 
@@ -36,6 +39,13 @@ pub struct TypingTracker {
     entries: VecDeque<TypingEntry>,
 }
 
+fn timestamp_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs()
+}
+
 impl TypingTracker {
     pub fn new() -> TypingTracker {
         TypingTracker {
@@ -43,24 +53,20 @@ impl TypingTracker {
         }
     }
 
-    /// Remove any expired entries
-    pub fn sweep(&mut self) {
-        let now = SystemTime::now();
-        let timestamp_now = now
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_secs() as u64;
-
-        // If the entry is more than 10 seconds old, remove it
-        // TODO: Use binary heap or other structure for better performance?
-        self.entries.retain(|e| timestamp_now - e.time < 10);
+    /// Remove any expired entries, returns whether any were removed
+    pub fn sweep(&mut self) -> bool {
+        let now = timestamp_now();
+        let len = self.entries.len();
+        self.entries.retain(|e| e.deadline > now);
+        self.entries.len() != len
     }
 
-    /// Add a new entry
+    /// Add a new entry, replacing any previous entry of the user in the same channel
     pub fn add(&mut self, entry: TypingEntry) {
+        self.entries
+            .retain(|e| e.user != entry.user || e.channel_id != entry.channel_id);
         self.entries.push_back(entry);
 
-        self.sweep();
         if self.entries.len() > MAX_TYPING_EVENTS {
             self.entries.pop_front();
         }
@@ -72,9 +78,10 @@ impl TypingTracker {
         guild_id: Option<Id<GuildMarker>>,
         channel_id: Id<ChannelMarker>,
     ) -> Vec<String> {
+        let now = timestamp_now();
         self.entries
             .iter()
-            .filter(|e| e.guild_id == guild_id && e.channel_id == channel_id)
+            .filter(|e| e.guild_id == guild_id && e.channel_id == channel_id && e.deadline > now)
             .map(|e| e.user_name.clone())
             .collect::<Vec<_>>()
     }
@@ -1,30 +1,51 @@
 use crate::{
     buffer::ext::BufferExt,
     config::Config,
-    discord::{discord_connection::DiscordConnection, typing_indicator::TypingTracker},
+    discord::{discord_connection::DiscordConnection, presence, typing_indicator::TypingTracker},
     instance::Instance,
     twilight_utils::ext::ChannelExt,
+    utils::emoji::style_emojis,
 };
+use std::time::Duration;
 use twilight_model::id::{
-    marker::{ChannelMarker, GuildMarker},
+    marker::{ChannelMarker, GuildMarker, UserMarker},
     Id,
 };
-use weechat::{buffer::Buffer, hooks::BarItem, Weechat};
+use weechat::{
+    buffer::Buffer,
+    hooks::{BarItem, TimerHook},
+    Weechat,
+};
 
 pub struct BarItems {
     _typing: BarItem,
+    _typing_expiry: TimerHook,
     _slowmode: BarItem,
     _readonly: BarItem,
+    _status: BarItem,
+    _nick_activity: BarItem,
 }
 
 impl BarItems {
     pub fn add_all(connection: DiscordConnection, instance: Instance, config: Config) -> BarItems {
+        // A single timer expires typing entries so that event handling never waits on them
+        let _typing_expiry = TimerHook::new(Duration::from_secs(1), 0, 0, {
+            let instance = instance.clone();
+            move |_: &Weechat, _: i32| {
+                if instance.borrow_typing_tracker_mut().sweep() {
+                    Weechat::bar_item_update("discord_typing");
+                }
+            }
+        })
+        .expect("Unable to hook typing expiry timer");
+
         let _typing = BarItem::new("discord_typing", {
+            let config = config.clone();
             move |_: &Weechat, buffer: &Buffer| {
                 if let Some(channel_id) = buffer.channel_id() {
                     let guild_id = buffer.guild_id();
 
-                    match config.typing_list_style() {
+                    let typing_list = match config.typing_list_style() {
                         0 => terse_typing_list(
                             &instance,
                             channel_id,
@@ -38,7 +59,8 @@ impl BarItems {
                             config.typing_list_max() as usize,
                         ),
                         _ => unreachable!(),
-                    }
+                    };
+                    style_emojis(&typing_list, config.emoji_style()).into_owned()
                 } else {
                     "".into()
                 }
@@ -79,6 +101,77 @@ impl BarItems {
         })
         .expect("Unable to create slowmode bar item");
 
+        let _status = BarItem::new("discord_status", {
+            let connection = connection.clone();
+            move |_: &Weechat, _: &Buffer| {
+                if connection.borrow().is_none() {
+                    return "".into();
+                }
+
+                let status = presence::status_name(config.status());
+                match config.custom_status() {
+                    Some(custom_status) => format!("{}: {}", status, custom_status),
+                    None => status.into(),
+                }
+            }
+        })
+        .expect("Unable to create status bar item");
+
+        let _nick_activity = BarItem::new("discord_nick_activity", {
+            let connection = connection.clone();
+            // The item is updated on every keystroke, so the member search is only repeated once
+            // the nick changes
+            let mut last_search: Option<(Id<GuildMarker>, String, Option<Id<UserMarker>>)> = None;
+            move |_: &Weechat, buffer: &Buffer| {
+                let connection = connection.borrow();
+                let connection = match connection.as_ref() {
+                    Some(conn) => conn,
+                    None => return "".into(),
+                };
+
+                let guild_id = match buffer.guild_id() {
+                    Some(guild_id) => guild_id,
+                    None => return "".into(),
+                };
+
+                // The nick being typed or completed
+                let input = buffer.input();
+                let nick = match input.split_whitespace().last() {
+                    Some(word) => word
+                        .trim_start_matches('@')
+                        .trim_end_matches(&[':', ','][..]),
+                    None => return "".into(),
+                };
+
+                let user_id = match &last_search {
+                    Some((last_guild_id, last_nick, user_id))
+                        if *last_guild_id == guild_id && last_nick == nick =>
+                    {
+                        *user_id
+                    },
+                    _ => {
+                        let user_id = crate::twilight_utils::search_cached_member_name(
+                            &connection.cache,
+                            guild_id,
+                            nick,
+                        );
+                        last_search = Some((guild_id, nick.to_owned(), user_id));
+                        user_id
+                    },
+                };
+
+                match user_id {
+                    Some(user_id) => format!(
+                        "{}: {}",
+                        nick,
+                        presence::describe_member_presence(&connection.cache, guild_id, user_id)
+                    ),
+                    None => "".into(),
+                }
+            }
+        })
+        .expect("Unable to create nick activity bar item");
+
         let _readonly = BarItem::new("discord_readonly", move |_: &Weechat, buffer: &Buffer| {
             let connection = connection.borrow();
             let connection = match connection.as_ref() {
@@ -107,8 +200,11 @@ impl BarItems {
 
         BarItems {
             _typing,
+            _typing_expiry,
             _slowmode,
             _readonly,
+            _status,
+            _nick_activity,
         }
     }
 }
@@ -1,13 +1,30 @@
 use crate::{
-    buffer::{ext::BufferExt, guild::Guild, pins::Pins},
+    buffer::{ext::BufferExt, forum::Forum, guild::Guild, pins::Pins},
     config::{Config, GuildConfig},
-    discord::discord_connection::DiscordConnection,
+    discord::{
+        discord_connection::{ConnectionInner, DiscordConnection},
+        presence::{self, CustomStatus},
+    },
     instance::Instance,
-    twilight_utils::ext::{ChannelExt, UserExt},
+    twilight_utils::{
+        ext::{CachedMemberExt, ChannelExt, MemberExt, UserExt},
+        Color,
+    },
+    weechat2::{Style, StyledString},
 };
-use std::borrow::Cow;
+use std::{borrow::Cow, path::PathBuf};
+use time::OffsetDateTime;
 use twilight_cache_inmemory::model::CachedGuild;
-use twilight_model::channel::{Channel as TwilightChannel, ChannelType};
+use twilight_model::{
+    channel::{Channel as TwilightChannel, ChannelType},
+    gateway::presence::Status,
+    guild::{Member, Permissions, Role},
+    id::{
+        marker::{GuildMarker, RoleMarker, UserMarker},
+        Id,
+    },
+    user::User,
+};
 use weechat::{
     buffer::Buffer,
     hooks::{Command, CommandRun, CommandSettings},
@@ -428,6 +445,303 @@ impl DiscordCommand {
         }
     }
 
+    /// Resolve the guild and parent channel of the current buffer, which may itself be a thread
+    fn resolve_thread_parent(&self, buffer: &Buffer) -> Option<(Guild, TwilightChannel)> {
+        let conn = self.connection.borrow();
+        let conn = match conn.as_ref() {
+            Some(conn) => conn,
+            None => {
+                Weechat::print("discord: must be connected to use threads");
+                return None;
+            },
+        };
+
+        let channel = match (buffer.guild_id(), buffer.channel_id()) {
+            (Some(_), Some(channel_id)) => conn
+                .cache
+                .channel(channel_id)
+                .map(|channel| channel.value().clone()),
+            _ => None,
+        };
+        let channel = match channel {
+            Some(channel) if channel.is_thread() => channel
+                .parent_id
+                .and_then(|parent_id| conn.cache.channel(parent_id))
+                .map(|parent| parent.value().clone()),
+            channel => channel,
+        };
+        let channel = match channel {
+            Some(channel) => channel,
+            None => {
+                Weechat::print("discord: this is not a discord server channel");
+                return None;
+            },
+        };
+
+        let guild = self
+            .instance
+            .borrow_guilds()
+            .get(&channel.guild_id?)
+            .cloned();
+        match guild {
+            Some(guild) => Some((guild, channel)),
+            None => {
+                Weechat::print("discord: this server has not been added to weechat");
+                None
+            },
+        }
+    }
+
+    fn list_threads(&self, buffer: &Buffer) {
+        let (guild, parent) = match self.resolve_thread_parent(buffer) {
+            Some(resolved) => resolved,
+            None => return,
+        };
+        let conn = self.connection.borrow();
+        let conn = match conn.as_ref() {
+            Some(conn) => conn,
+            None => return,
+        };
+
+        let threads =
+            crate::twilight_utils::cached_channel_threads(&conn.cache, guild.id, parent.id);
+        if threads.is_empty() {
+            buffer.print(&format!(
+                "discord: #{} has no active threads",
+                parent.name()
+            ));
+            return;
+        }
+
+        buffer.print(&format!("discord: active threads in #{}:", parent.name()));
+        for thread in threads {
+            let joined = self
+                .instance
+                .search_buffer(Some(guild.id), thread.id)
+                .is_some();
+            buffer.print(&format!(
+                "  {}{}",
+                thread.name(),
+                if joined { " (joined)" } else { "" }
+            ));
+        }
+    }
+
+    fn join_thread(&self, matches: ParsedCommand, buffer: &Buffer) {
+        let name = matches.arg("name").expect("enforced by validation");
+        let (guild, parent) = match self.resolve_thread_parent(buffer) {
+            Some(resolved) => resolved,
+            None => return,
+        };
+        let conn = match self.connection.borrow().as_ref() {
+            Some(conn) => conn.clone(),
+            None => return,
+        };
+
+        let thread = match crate::twilight_utils::search_cached_stripped_thread_name(
+            &conn.cache,
+            guild.id,
+            parent.id,
+            name,
+        ) {
+            Some(thread) => thread,
+            None => {
+                buffer.print(&format!(
+                    "discord: could not find thread \"{}\" in #{}",
+                    name,
+                    parent.name()
+                ));
+                return;
+            },
+        };
+
+        let buffer_name = buffer.full_name().to_string();
+        Weechat::spawn(async move {
+            let result = conn
+                .rt
+                .spawn({
+                    let http = conn.http.clone();
+                    let thread_id = thread.id;
+                    async move { http.join_thread(thread_id).exec().await }
+                })
+                .await
+                .expect("Task is never aborted");
+
+            let result = match result {
+                Ok(_) => guild.join_channel(&thread),
+                Err(e) => Err(e.into()),
+            };
+            if let Err(e) = result {
+                tracing::error!(thread.id=?thread.id, "Unable to join thread: {}", e);
+                if let Some(buffer) =
+                    unsafe { Weechat::weechat() }.buffer_search("==", &buffer_name)
+                {
+                    buffer.print(&format!(
+                        "discord: unable to join thread \"{}\": {}",
+                        thread.name(),
+                        e
+                    ));
+                }
+            }
+        })
+        .detach();
+    }
+
+    fn leave_thread(&self, buffer: &Buffer) {
+        let conn = match self.connection.borrow().as_ref() {
+            Some(conn) => conn.clone(),
+            None => {
+                Weechat::print("discord: must be connected to use threads");
+                return;
+            },
+        };
+
+        let is_thread = buffer
+            .channel_id()
+            .and_then(|channel_id| conn.cache.channel(channel_id))
+            .map(|channel| channel.is_thread())
+            .unwrap_or(false);
+        let channel = match buffer.channel_id() {
+            Some(channel_id) if is_thread => {
+                self.instance.search_buffer(buffer.guild_id(), channel_id)
+            },
+            _ => None,
+        };
+        let channel = match channel {
+            Some(channel) => channel,
+            None => {
+                buffer.print("discord: this is not a thread buffer");
+                return;
+            },
+        };
+
+        Weechat::spawn(async move {
+            let result = conn
+                .rt
+                .spawn({
+                    let http = conn.http.clone();
+                    let thread_id = channel.id;
+                    async move { http.leave_thread(thread_id).exec().await }
+                })
+                .await
+                .expect("Task is never aborted");
+
+            match result {
+                Ok(_) => channel.close(),
+                Err(e) => {
+                    tracing::error!(thread.id=?channel.id, "Unable to leave thread: {}", e);
+                    channel.print(&format!("discord: unable to leave thread: {}", e));
+                },
+            }
+        })
+        .detach();
+    }
+
+    fn process_thread_matches(&self, matches: ParsedCommand, buffer: &Buffer) {
+        match matches.subcommand() {
+            Some(("list", _)) => self.list_threads(buffer),
+            Some(("join", matches)) => self.join_thread(matches, buffer),
+            Some(("leave", _)) => self.leave_thread(buffer),
+            _ => {},
+        }
+    }
+
+    fn open_forum(&self, matches: ParsedCommand) {
+        let guild_name = matches.arg("guild_name").expect("enforced by validation");
+        let forum_name = matches.arg("name").expect("enforced by validation");
+
+        let conn = match self.connection.borrow().as_ref() {
+            Some(conn) => conn.clone(),
+            None => {
+                Weechat::print("discord: must be connected to open forums");
+                return;
+            },
+        };
+
+        let guild = match crate::twilight_utils::search_cached_striped_guild_name(
+            &conn.cache,
+            guild_name,
+        ) {
+            Some(guild) => guild,
+            None => {
+                Weechat::print(&format!(
+                    "discord: could not find server: \"{}\"",
+                    guild_name
+                ));
+                return;
+            },
+        };
+        let forum_channel = match crate::twilight_utils::search_cached_stripped_forum_name(
+            &conn.cache,
+            guild.id(),
+            forum_name,
+        ) {
+            Some(forum_channel) => forum_channel,
+            None => {
+                Weechat::print(&format!(
+                    "discord: could not find forum: \"{}\"",
+                    forum_name
+                ));
+                return;
+            },
+        };
+
+        let forum = Forum::new(guild.id(), forum_channel.id, conn, self.instance.clone());
+        let instance = self.instance.clone();
+        Weechat::spawn(async move {
+            if let Err(e) = forum.load().await {
+                tracing::error!(
+                    guild.id=?forum.guild_id,
+                    channel.id=?forum.channel_id,
+                    "Unable to load forum: {}",
+                    e
+                );
+
+                Weechat::print(&format!("discord: an error occurred loading forum: {}", e));
+                return;
+            };
+
+            let _old = instance.borrow_forums_mut().insert(forum.channel_id, forum);
+        })
+        .detach();
+    }
+
+    fn forum_post(&self, matches: ParsedCommand, buffer: &Buffer, raw: &str) {
+        let title = matches.rest(raw).trim();
+        if title.is_empty() {
+            buffer.print("discord: usage: /discord forum post <title>");
+            return;
+        }
+
+        let forum = buffer
+            .channel_id()
+            .and_then(|channel_id| self.instance.borrow_forums().get(&channel_id).cloned());
+        let forum = match forum {
+            Some(forum) => forum,
+            None => {
+                buffer.print("discord: this is not a forum buffer");
+                return;
+            },
+        };
+
+        let body = buffer.input().trim().to_owned();
+        if body.is_empty() {
+            buffer.print("discord: enter the body of the post in the input line first");
+            return;
+        }
+        buffer.set_input("");
+
+        forum.post(title.to_owned(), body);
+    }
+
+    fn process_forum_matches(&self, matches: ParsedCommand, buffer: &Buffer, raw: &str) {
+        match matches.subcommand() {
+            Some(("open", matches)) => self.open_forum(matches),
+            Some(("post", matches)) => self.forum_post(matches, buffer, raw),
+            _ => {},
+        }
+    }
+
     fn token(&self, matches: ParsedCommand) {
         let token = matches.arg("token").expect("enforced by validation");
 
@@ -484,6 +798,45 @@ impl DiscordCommand {
         tracing::info!("Unable to find user \"{}\"", user);
     }
 
+    fn whois(&self, matches: ParsedCommand, buffer: &Buffer) {
+        let target = matches.arg("user").expect("enforced by validation");
+
+        let conn = match self.connection.borrow().as_ref() {
+            Some(conn) => conn.clone(),
+            None => {
+                buffer.print("discord: must be connected to look up users");
+                return;
+            },
+        };
+
+        let guild_id = buffer.guild_id();
+        let user_id = match crate::twilight_utils::search_cached_user(&conn.cache, guild_id, target)
+        {
+            Some(user_id) => user_id,
+            None => {
+                buffer.print(&format!("discord: unable to find user \"{}\"", target));
+                return;
+            },
+        };
+
+        let buffer_name = buffer.full_name().to_string();
+        Weechat::spawn(async move {
+            let lines = match whois_lines(&conn, guild_id, user_id).await {
+                Ok(lines) => lines,
+                Err(e) => {
+                    tracing::error!(user.id=?user_id, "Unable to fetch user: {}", e);
+                    vec![format!("discord: unable to fetch user: {}", e)]
+                },
+            };
+            if let Some(buffer) = unsafe { Weechat::weechat() }.buffer_search("==", &buffer_name) {
+                for line in lines {
+                    buffer.print(&line);
+                }
+            }
+        })
+        .detach();
+    }
+
     fn pins(&self, weechat: &Weechat) {
         let conn = self.connection.borrow();
         let conn = match conn.as_ref() {
@@ -516,9 +869,7 @@ impl DiscordCommand {
                 ));
             };
 
-            let _old = instance
-                .borrow_pins_mut()
-                .insert((guild_id, channel_id.unwrap()), pins);
+            let _old = instance.insert_pins(pins);
         })
         .detach();
     }
@@ -542,6 +893,237 @@ impl DiscordCommand {
         );
     }
 
+    fn upload(&self, matches: ParsedCommand, buffer: &Buffer, raw: &str) {
+        let conn = self.connection.borrow();
+        let conn = match conn.as_ref() {
+            Some(conn) => conn.clone(),
+            None => {
+                buffer.print("discord: must be connected to upload files");
+                return;
+            },
+        };
+
+        let channel = match buffer
+            .channel_id()
+            .and_then(|channel_id| self.instance.search_buffer(buffer.guild_id(), channel_id))
+        {
+            Some(channel) => channel,
+            None => {
+                Weechat::print("discord: this is not a discord buffer");
+                return;
+            },
+        };
+
+        let rest = matches.rest(raw).trim();
+        let (path, caption) = match rest.split_once(char::is_whitespace) {
+            Some((path, caption)) => (path, caption.trim_start()),
+            None => (rest, ""),
+        };
+        if path.is_empty() {
+            buffer.print("discord: a file path is required");
+            return;
+        }
+        let path = match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+            (Some(relative), Some(home)) => PathBuf::from(home).join(relative),
+            _ => PathBuf::from(path),
+        };
+
+        if let Some(false) = conn
+            .cache
+            .channel(channel.id)
+            .and_then(|channel| channel.has_permission(&conn.cache, Permissions::ATTACH_FILES))
+        {
+            buffer.print("discord: you don't have permission to upload files in this channel");
+            return;
+        }
+
+        let size = match std::fs::metadata(&path) {
+            Ok(metadata) if metadata.is_file() => metadata.len(),
+            Ok(_) => {
+                buffer.print(&format!("discord: \"{}\" is not a file", path.display()));
+                return;
+            },
+            Err(e) => {
+                buffer.print(&format!(
+                    "discord: unable to read \"{}\": {}",
+                    path.display(),
+                    e
+                ));
+                return;
+            },
+        };
+        let max_size = crate::twilight_utils::max_upload_size(&conn.cache, buffer.guild_id());
+        if size > max_size {
+            buffer.print(&format!(
+                "discord: \"{}\" is too large to upload ({} > {})",
+                path.display(),
+                humanize_size(size),
+                humanize_size(max_size)
+            ));
+            return;
+        }
+
+        let file = match std::fs::read(&path) {
+            Ok(file) => file,
+            Err(e) => {
+                buffer.print(&format!(
+                    "discord: unable to read \"{}\": {}",
+                    path.display(),
+                    e
+                ));
+                return;
+            },
+        };
+        let filename = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "file".to_owned());
+
+        let caption = caption.to_owned();
+        Weechat::spawn(async move { channel.upload(filename, file, &caption).await }).detach();
+    }
+
+    fn set_status(&self, status: Status) {
+        self.config.set_status(status);
+        self.config.persist();
+        self.send_presence();
+    }
+
+    fn set_custom_status(&self, matches: ParsedCommand, raw: &str) {
+        let mut words: Vec<_> = matches.rest(raw).split_whitespace().collect();
+
+        let expiry = match words.last().and_then(|word| presence::parse_expiry(word)) {
+            Some(expiry) => {
+                words.pop();
+                Some(expiry)
+            },
+            None => None,
+        };
+        // Emoji are the only words without any letters, digits or ascii punctuation
+        let emoji = match words.last() {
+            Some(word)
+                if !word
+                    .chars()
+                    .any(|c| c.is_alphanumeric() || c.is_ascii_punctuation()) =>
+            {
+                words.pop().map(ToOwned::to_owned)
+            },
+            _ => None,
+        };
+        let text = words.join(" ");
+
+        let custom_status = if text.is_empty() && emoji.is_none() {
+            None
+        } else {
+            Some(CustomStatus {
+                text,
+                emoji,
+                expires_at: expiry.map(|expiry| {
+                    OffsetDateTime::now_utc().unix_timestamp() + expiry.as_secs() as i64
+                }),
+            })
+        };
+        self.config.set_custom_status(custom_status);
+        self.config.persist();
+        self.send_presence();
+
+        let (expiry, rt) = match (expiry, self.connection.borrow().as_ref()) {
+            (Some(expiry), Some(conn)) => (expiry, conn.rt.clone()),
+            _ => return,
+        };
+        let connection = self.connection.clone();
+        let config = self.config.clone();
+        Weechat::spawn(async move {
+            rt.spawn(tokio::time::sleep(expiry))
+                .await
+                .expect("Task is never aborted");
+
+            // A replacement status would still be set, so the status is only cleared if expired
+            if config.custom_status().is_none() {
+                config.set_custom_status(None);
+                config.persist();
+                let presence = presence::presence(config.status(), None);
+                if let Err(e) = connection.update_presence(presence).await {
+                    tracing::warn!("Unable to clear expired custom status: {}", e);
+                }
+                Weechat::bar_item_update("discord_status");
+            }
+        })
+        .detach();
+    }
+
+    fn send_presence(&self) {
+        let connection = self.connection.clone();
+        let presence =
+            presence::presence(self.config.status(), self.config.custom_status().as_ref());
+        Weechat::bar_item_update("discord_status");
+        Weechat::spawn(async move {
+            if let Err(e) = connection.update_presence(presence).await {
+                tracing::error!("Unable to update presence: {}", e);
+                Weechat::print(&format!("discord: unable to update status: {}", e));
+            }
+        })
+        .detach();
+    }
+
+    fn process_status_matches(&self, matches: ParsedCommand, raw: &str) {
+        match matches.subcommand() {
+            Some(("custom", matches)) => self.set_custom_status(matches, raw),
+            Some((status, _)) => {
+                if let Some(status) = presence::parse_status(status) {
+                    self.set_status(status);
+                }
+            },
+            None => {
+                let mut status = presence::status_name(self.config.status()).to_owned();
+                if let Some(custom_status) = self.config.custom_status() {
+                    status = format!("{} ({})", status, custom_status);
+                }
+                Weechat::print(&format!("discord: status: {}", status));
+            },
+        }
+    }
+
+    /// Reveal hidden spoilers with `reveal <n>` or `toggle`, anything else is sent as a spoiler
+    fn spoiler(&self, matches: ParsedCommand, weechat: &Weechat, buffer: &Buffer, raw: &str) {
+        let rest = matches.rest(raw).trim();
+        let reveal = rest
+            .strip_prefix("reveal ")
+            .map(str::trim_start)
+            .filter(|line| line.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|line| line.parse::<usize>().ok());
+        if reveal.is_none() && rest != "toggle" {
+            self.discord_format(matches, weechat, raw);
+            return;
+        }
+
+        let channel = match buffer
+            .channel_id()
+            .and_then(|channel_id| self.instance.search_buffer(buffer.guild_id(), channel_id))
+        {
+            Some(channel) => channel,
+            None => {
+                Weechat::print("discord: Not a Discord buffer");
+                return;
+            },
+        };
+
+        match reveal {
+            Some(line) => {
+                if !channel.reveal_spoilers(line) {
+                    buffer.print(&format!("discord: no message at offset {}", line));
+                }
+            },
+            None => {
+                if channel.toggle_reveal_all_spoilers() {
+                    buffer.print("discord: revealing all spoilers in this buffer");
+                } else {
+                    buffer.print("discord: hiding spoilers in this buffer");
+                }
+            },
+        }
+    }
+
     fn discord_format(&self, matches: ParsedCommand, weechat: &Weechat, raw: &str) {
         let conn = self.connection.borrow();
         let conn = match conn.as_ref() {
@@ -639,6 +1221,15 @@ impl DiscordCommand {
                         strng, weak, pins.guild_id, pins.channel_id
                     ));
                 }
+
+                for forum in self.instance.borrow_forums().values() {
+                    let (strng, weak) = forum.debug_counts();
+
+                    Weechat::print(&format!(
+                        "Forum [{} {}]: {} {}",
+                        strng, weak, forum.guild_id, forum.channel_id
+                    ));
+                }
             },
             Some(("members", _)) => {
                 let conn = self.connection.borrow();
@@ -675,6 +1266,8 @@ impl DiscordCommand {
                     .drain()
                     .collect();
                 let _pins: Vec<_> = self.instance.borrow_pins_mut().drain().collect();
+                self.instance.clear_routes();
+                let _forums: Vec<_> = self.instance.borrow_forums_mut().drain().collect();
             },
             _ => {},
         }
@@ -712,7 +1305,31 @@ impl weechat::hooks::CommandCallback for DiscordCommand {
                             .arg("name", true),
                     ),
             )
+            .subcommand(
+                WeechatCommand::new("thread")
+                    .subcommand(WeechatCommand::new("list"))
+                    .subcommand(WeechatCommand::new("join").arg("name", true))
+                    .subcommand(WeechatCommand::new("leave")),
+            )
+            .subcommand(
+                WeechatCommand::new("forum")
+                    .subcommand(
+                        WeechatCommand::new("open")
+                            .arg("guild_name", true)
+                            .arg("name", true),
+                    )
+                    .subcommand(WeechatCommand::new("post")),
+            )
             .subcommand(WeechatCommand::new("query").arg("user", true))
+            .subcommand(WeechatCommand::new("whois").arg("user", true))
+            .subcommand(
+                WeechatCommand::new("status")
+                    .subcommand(WeechatCommand::new("online"))
+                    .subcommand(WeechatCommand::new("idle"))
+                    .subcommand(WeechatCommand::new("dnd"))
+                    .subcommand(WeechatCommand::new("invisible"))
+                    .subcommand(WeechatCommand::new("custom")),
+            )
             .subcommand(
                 WeechatCommand::new("debug")
                     .subcommand(WeechatCommand::new("buffer"))
@@ -723,6 +1340,7 @@ impl weechat::hooks::CommandCallback for DiscordCommand {
             .subcommand(WeechatCommand::new("token").arg("token", true))
             .subcommand(WeechatCommand::new("pins"))
             .subcommand(WeechatCommand::new("more_history"))
+            .subcommand(WeechatCommand::new("upload"))
             .subcommand(WeechatCommand::new("me"))
             .subcommand(WeechatCommand::new("tableflip"))
             .subcommand(WeechatCommand::new("unflip"))
@@ -745,16 +1363,23 @@ impl weechat::hooks::CommandCallback for DiscordCommand {
         match matches.subcommand() {
             Some(("server", matches)) => self.process_server_matches(matches),
             Some(("channel", matches)) => self.process_channel_matches(matches),
+            Some(("thread", matches)) => self.process_thread_matches(matches, buffer),
+            Some(("forum", matches)) => {
+                self.process_forum_matches(matches, buffer, &args.join(" "))
+            },
             Some(("token", matches)) => self.token(matches),
             Some(("query", matches)) => self.query(matches),
+            Some(("whois", matches)) => self.whois(matches, buffer),
+            Some(("status", matches)) => self.process_status_matches(matches, &args.join(" ")),
             Some(("pins", _)) => self.pins(weechat),
             Some(("more_history", _)) => self.more_history(buffer),
+            Some(("upload", matches)) => self.upload(matches, buffer, &args.join(" ")),
             // Use or-patterns when they stabilize (rust #54883)
             Some(("me", matches))
             | Some(("tableflip", matches))
             | Some(("unflip", matches))
-            | Some(("shrug", matches))
-            | Some(("spoiler", matches)) => self.discord_format(matches, weechat, &args.join(" ")),
+            | Some(("shrug", matches)) => self.discord_format(matches, weechat, &args.join(" ")),
+            Some(("spoiler", matches)) => self.spoiler(matches, weechat, buffer, &args.join(" ")),
             Some(("debug", matches)) => self.process_debug_matches(matches, weechat),
             _ => {},
         };
@@ -768,18 +1393,34 @@ pub fn hook(connection: DiscordConnection, instance: Instance, config: Config) -
             .add_argument("token <token>")
             .add_argument("server add|remove|list|autoconnect|noautoconnect <server-name>")
             .add_argument("channel join|autojoin|noautojoin <server-name> <channel-name>")
+            .add_argument("thread list|join|leave <thread-name>")
+            .add_argument("forum open <server-name> <forum-name>")
+            .add_argument("forum post <title>")
             .add_argument("query <user-name>")
+            .add_argument("whois <user>")
+            .add_argument("status online|idle|dnd|invisible")
+            .add_argument("status custom [<text>] [<emoji>] [<expiry>]")
             .add_argument("pins")
             .add_argument("more_history")
+            .add_argument("upload <path> [<caption>]")
             .add_argument("me|tableflip|unflip|shrug|spoiler")
+            .add_argument("spoiler reveal <n>|toggle")
             .add_argument("debug buffer|buffers|shutdown|members")
             .add_completion("token")
             .add_completion("server add|remove|list|autoconnect|noautoconnect %(discord_guild)")
             .add_completion("channel join|autojoin|noautojoin %(discord_guild) %(discord_channel)")
+            .add_completion("thread list|leave")
+            .add_completion("thread join %(discord_thread)")
+            .add_completion("forum open %(discord_guild)")
+            .add_completion("forum post")
             .add_completion("query %(discord_dm)")
+            .add_completion("whois %(discord_user)")
+            .add_completion("status online|idle|dnd|invisible|custom")
             .add_completion("pins")
             .add_completion("more_history")
+            .add_completion("upload %(filename)")
             .add_completion("me|tableflip|unflip|shrug|spoiler")
+            .add_completion("spoiler reveal|toggle")
             .add_completion("debug buffer|shutdown|members"),
         DiscordCommand {
             instance,
@@ -803,3 +1444,173 @@ pub fn hook(connection: DiscordConnection, instance: Instance, config: Config) -
         _me_hook,
     }
 }
+
+/// The profile of a guild member, from the cache or the api
+struct WhoisMember {
+    nick: Option<String>,
+    roles: Vec<Id<RoleMarker>>,
+    joined_at: i64,
+    color: Option<Color>,
+    highest_role: Option<Role>,
+}
+
+/// Format a user's profile like an irc whois reply
+async fn whois_lines(
+    conn: &ConnectionInner,
+    guild_id: Option<Id<GuildMarker>>,
+    user_id: Id<UserMarker>,
+) -> anyhow::Result<Vec<String>> {
+    let cache = &conn.cache;
+
+    let user = match cache.user(user_id) {
+        Some(user) => user.value().clone(),
+        None => {
+            let result: anyhow::Result<User> = conn
+                .rt
+                .spawn({
+                    let http = conn.http.clone();
+                    async move { Ok(http.user(user_id).exec().await?.model().await?) }
+                })
+                .await
+                .expect("Task is never aborted");
+            result?
+        },
+    };
+
+    let cached_member = guild_id.and_then(|guild_id| {
+        cache.member(guild_id, user_id).map(|member| WhoisMember {
+            nick: member.nick().map(ToOwned::to_owned),
+            roles: member.roles().to_vec(),
+            joined_at: member.joined_at().as_secs(),
+            color: member.color(cache),
+            highest_role: member.highest_role_info(cache),
+        })
+    });
+    let member = match (cached_member, guild_id) {
+        (Some(member), _) => Some(member),
+        (None, Some(guild_id)) => {
+            let result: anyhow::Result<Member> = conn
+                .rt
+                .spawn({
+                    let http = conn.http.clone();
+                    async move {
+                        Ok(http
+                            .guild_member(guild_id, user_id)
+                            .exec()
+                            .await?
+                            .model()
+                            .await?)
+                    }
+                })
+                .await
+                .expect("Task is never aborted");
+            // Not being a member of the guild is not an error
+            result.ok().map(|member| WhoisMember {
+                nick: member.nick.clone(),
+                roles: member.roles.clone(),
+                joined_at: member.joined_at.as_secs(),
+                color: member.color(cache),
+                highest_role: member.highest_role_info(cache),
+            })
+        },
+        (None, None) => None,
+    };
+
+    let name = member
+        .as_ref()
+        .and_then(|member| member.nick.as_deref())
+        .unwrap_or(&user.name);
+    let nick = match member.as_ref().and_then(|member| member.color) {
+        Some(color) => crate::utils::color::colorize_string(name, &color.as_8bit().to_string()),
+        None => StyledString::from(name),
+    };
+    let prefix = format!(
+        "{}\t[{}] ",
+        Weechat::prefix(weechat::Prefix::Network),
+        nick.build()
+    );
+
+    let mut lines = vec![format!(
+        "{}{} ({}){}",
+        prefix,
+        user.tag(),
+        user.id,
+        if user.bot { " [bot]" } else { "" }
+    )];
+
+    if let Some(member) = &member {
+        if let Some(nick) = &member.nick {
+            lines.push(format!("{}  nickname: {}", prefix, nick));
+        }
+        if !member.roles.is_empty() {
+            let mut roles = StyledString::new();
+            for (i, role) in member
+                .roles
+                .iter()
+                .filter_map(|role_id| cache.role(*role_id))
+                .enumerate()
+            {
+                if i > 0 {
+                    roles.push_str(" ");
+                }
+                let name = format!("@{}", role.name);
+                if role.color == 0 {
+                    roles.push_str(&name);
+                } else {
+                    roles.push_styled_str(
+                        Style::color(&Color::new(role.color).as_8bit().to_string()),
+                        &name,
+                    );
+                }
+            }
+            lines.push(format!("{}  roles: {}", prefix, roles.build()));
+        }
+        if let Some(highest_role) = &member.highest_role {
+            lines.push(format!("{}  top role: @{}", prefix, highest_role.name));
+        }
+        lines.push(format!(
+            "{}  joined: {} ({})",
+            prefix,
+            crate::utils::fmt_timestamp(member.joined_at, 'f'),
+            crate::utils::fmt_timestamp(member.joined_at, 'R')
+        ));
+    }
+
+    let created_at = crate::twilight_utils::snowflake_timestamp(user_id.get());
+    lines.push(format!(
+        "{}  account created: {} ({})",
+        prefix,
+        crate::utils::fmt_timestamp(created_at, 'f'),
+        crate::utils::fmt_timestamp(created_at, 'R')
+    ));
+
+    if let Some(guild_id) = guild_id {
+        lines.push(format!(
+            "{}  status: {}",
+            prefix,
+            presence::describe_member_presence(cache, guild_id, user_id)
+        ));
+    }
+
+    let mutual_guilds: Vec<_> = cache
+        .iter()
+        .guilds()
+        .filter(|guild| cache.member(guild.id(), user_id).is_some())
+        .map(|guild| guild.name().to_owned())
+        .collect();
+    if !mutual_guilds.is_empty() {
+        lines.push(format!(
+            "{}  mutual servers: {}",
+            prefix,
+            mutual_guilds.join(", ")
+        ));
+    }
+
+    lines.push(format!("{}End of WHOIS", prefix));
+    Ok(lines)
+}
+
+fn humanize_size(bytes: u64) -> String {
+    const MIB: f64 = 1024.0 * 1024.0;
+    format!("{:.1} MiB", bytes as f64 / MIB)
+}
@@ -1,24 +1,32 @@
 use crate::{
+    buffer::ext::BufferExt,
     discord::discord_connection::DiscordConnection,
-    twilight_utils::ext::{ChannelExt, UserExt},
+    instance::Instance,
+    twilight_utils::ext::{CachedMemberExt, ChannelExt, UserExt},
     utils,
 };
 use std::borrow::Cow;
-use twilight_model::channel::ChannelType;
+use twilight_model::{channel::ChannelType, gateway::payload::incoming::MemberListItem};
 use weechat::{
     buffer::Buffer,
-    hooks::{Completion, CompletionHook},
+    hooks::{Completion, CompletionHook, CompletionPosition},
     Weechat,
 };
 
 pub struct Completions {
     _guild_completion_hook: CompletionHook,
     _channel_completion_hook: CompletionHook,
+    _thread_completion_hook: CompletionHook,
     _dm_completion_hook: CompletionHook,
+    _user_completion_hook: CompletionHook,
+    _nick_completion_hook: CompletionHook,
+    _channel_mention_completion_hook: CompletionHook,
+    _role_completion_hook: CompletionHook,
+    _emoji_completion_hook: CompletionHook,
 }
 
 impl Completions {
-    pub fn hook_all(connection: DiscordConnection) -> Completions {
+    pub fn hook_all(connection: DiscordConnection, instance: Instance) -> Completions {
         let connection_clone = connection.clone();
         let _guild_completion_hook = CompletionHook::new(
             "discord_guild",
@@ -96,7 +104,37 @@ impl Completions {
         )
         .expect("Unable to hook discord channel completion");
 
-        let connection_clone = connection;
+        let connection_clone = connection.clone();
+        let _thread_completion_hook = CompletionHook::new(
+            "discord_thread",
+            "Completion for the threads of the current Discord channel",
+            move |_: &Weechat, buffer: &Buffer, _: Cow<str>, completion: &Completion| {
+                let (guild_id, channel_id) = match (buffer.guild_id(), buffer.channel_id()) {
+                    (Some(guild_id), Some(channel_id)) => (guild_id, channel_id),
+                    _ => return Ok(()),
+                };
+                if let Some(connection) = connection_clone.borrow().as_ref() {
+                    let cache = &connection.cache;
+                    // Threads of the parent channel are offered in a thread buffer
+                    let parent_id = match cache.channel(channel_id) {
+                        Some(channel) if channel.is_thread() => match channel.parent_id {
+                            Some(parent_id) => parent_id,
+                            None => return Ok(()),
+                        },
+                        _ => channel_id,
+                    };
+                    for thread in
+                        crate::twilight_utils::cached_channel_threads(cache, guild_id, parent_id)
+                    {
+                        completion.add(&utils::clean_name(&thread.name()));
+                    }
+                }
+                Ok(())
+            },
+        )
+        .expect("Unable to hook discord thread completion");
+
+        let connection_clone = connection.clone();
         let _dm_completion_hook = CompletionHook::new(
             "discord_dm",
             "Completion for Discord private channels",
@@ -125,10 +163,216 @@ impl Completions {
         )
         .expect("Unable to hook discord guild completion");
 
+        let connection_clone = connection.clone();
+        let _user_completion_hook = CompletionHook::new(
+            "discord_user",
+            "Completion for Discord users, members of the current server if any",
+            move |_: &Weechat, buffer: &Buffer, _: Cow<str>, completion: &Completion| {
+                let connection = connection_clone.borrow();
+                let connection = match connection.as_ref() {
+                    Some(connection) => connection,
+                    None => return Err(()),
+                };
+                let cache = &connection.cache;
+
+                match buffer.guild_id() {
+                    Some(guild_id) => {
+                        if let Some(members) = cache.guild_members(guild_id) {
+                            for user_id in members.iter() {
+                                if let Some(user) = cache.user(*user_id) {
+                                    completion.add(&user.name);
+                                }
+                            }
+                        }
+                    },
+                    None => {
+                        for user in cache.iter().users() {
+                            completion.add(&utils::clean_name_with_case(&user.tag()));
+                        }
+                    },
+                }
+                Ok(())
+            },
+        )
+        .expect("Unable to hook discord user completion");
+
+        let connection_clone = connection.clone();
+        let _nick_completion_hook = CompletionHook::new(
+            "discord_nick",
+            "Completion for nicks of the current channel, plain or prefixed with @",
+            move |_: &Weechat, buffer: &Buffer, _: Cow<str>, completion: &Completion| {
+                let connection = connection_clone.borrow();
+                let connection = match connection.as_ref() {
+                    Some(connection) => connection,
+                    None => return Err(()),
+                };
+                let cache = &connection.cache;
+                let channel_id = match buffer.channel_id() {
+                    Some(channel_id) => channel_id,
+                    None => return Ok(()),
+                };
+                let guild_id = buffer.guild_id();
+
+                let recent_authors = instance
+                    .search_buffer(guild_id, channel_id)
+                    .map(|channel| channel.recent_authors())
+                    .unwrap_or_default();
+                let mut members = Vec::new();
+                if let Some(guild_id) = guild_id {
+                    if let Some(list) = instance
+                        .borrow_member_lists()
+                        .get(&guild_id)
+                        .and_then(|lists| lists.get_list_for_channel(channel_id, cache))
+                    {
+                        members.extend(list.iter().filter_map(|item| match item {
+                            MemberListItem::Member(member) => Some(member.user.id),
+                            MemberListItem::Group(_) => None,
+                        }));
+                    }
+                } else if let Some(channel) = cache.channel(channel_id) {
+                    members.extend(channel.recipients.iter().flatten().map(|user| user.id));
+                }
+
+                // Recent authors are offered first, the most recent one at the very beginning
+                let mut added = Vec::new();
+                let users = recent_authors
+                    .iter()
+                    .rev()
+                    .map(|user_id| (*user_id, CompletionPosition::Beginning))
+                    .chain(
+                        members
+                            .iter()
+                            .map(|user_id| (*user_id, CompletionPosition::Sorted)),
+                    );
+                for (user_id, position) in users {
+                    let username = match cache.user(user_id) {
+                        Some(user) => user.name.clone(),
+                        None => continue,
+                    };
+                    let nick = guild_id
+                        .and_then(|guild_id| cache.member(guild_id, user_id))
+                        .map(|member| member.display_name(cache))
+                        // Names with spaces can't be mentioned, the username is used instead
+                        .filter(|nick| !nick.contains(char::is_whitespace));
+
+                    for name in nick.into_iter().chain(std::iter::once(username)) {
+                        if added.contains(&name) {
+                            continue;
+                        }
+                        completion.add_with_options(&name, true, position);
+                        completion.add_with_options(&format!("@{}", name), false, position);
+                        added.push(name);
+                    }
+                }
+                Ok(())
+            },
+        )
+        .expect("Unable to hook discord nick completion");
+
+        let connection_clone = connection.clone();
+        let _channel_mention_completion_hook = CompletionHook::new(
+            "discord_channel_mention",
+            "Completion for channels and threads of the current server, prefixed with #",
+            move |_: &Weechat, buffer: &Buffer, _: Cow<str>, completion: &Completion| {
+                let connection = connection_clone.borrow();
+                let connection = match connection.as_ref() {
+                    Some(connection) => connection,
+                    None => return Err(()),
+                };
+                let cache = &connection.cache;
+                let guild_id = match buffer.guild_id() {
+                    Some(guild_id) => guild_id,
+                    None => return Ok(()),
+                };
+
+                if let Some(channels) = cache.guild_channels(guild_id) {
+                    for channel_id in channels.iter() {
+                        if let Some(channel) = cache.channel(*channel_id) {
+                            let name = channel.name();
+                            // Names with spaces can't be converted to mentions
+                            if !channel.is_text_channel(cache) || name.contains(char::is_whitespace)
+                            {
+                                continue;
+                            }
+                            completion.add(&format!("#{}", name));
+                        }
+                    }
+                }
+                Ok(())
+            },
+        )
+        .expect("Unable to hook discord channel mention completion");
+
+        let connection_clone = connection.clone();
+        let _role_completion_hook = CompletionHook::new(
+            "discord_role",
+            "Completion for roles of the current server, prefixed with @",
+            move |_: &Weechat, buffer: &Buffer, _: Cow<str>, completion: &Completion| {
+                let connection = connection_clone.borrow();
+                let connection = match connection.as_ref() {
+                    Some(connection) => connection,
+                    None => return Err(()),
+                };
+                let cache = &connection.cache;
+                let guild_id = match buffer.guild_id() {
+                    Some(guild_id) => guild_id,
+                    None => return Ok(()),
+                };
+
+                if let Some(roles) = cache.guild_roles(guild_id) {
+                    for role_id in roles.iter() {
+                        // The @everyone role shares the id of the guild
+                        if role_id.cast() == guild_id {
+                            continue;
+                        }
+                        if let Some(role) = cache.role(*role_id) {
+                            if !role.name.contains(char::is_whitespace) {
+                                completion.add(&format!("@{}", role.name));
+                            }
+                        }
+                    }
+                }
+                Ok(())
+            },
+        )
+        .expect("Unable to hook discord role completion");
+
+        let connection_clone = connection;
+        let _emoji_completion_hook = CompletionHook::new(
+            "discord_emoji",
+            "Completion for emoji shortcodes, including the custom emojis of the current server",
+            move |_: &Weechat, buffer: &Buffer, _: Cow<str>, completion: &Completion| {
+                if let Some(guild_id) = buffer.guild_id() {
+                    if let Some(connection) = connection_clone.borrow().as_ref() {
+                        let cache = &connection.cache;
+                        if let Some(emojis) = cache.guild_emojis(guild_id) {
+                            for emoji_id in emojis.iter() {
+                                if let Some(emoji) = cache.emoji(*emoji_id) {
+                                    completion.add(&format!(":{}:", emoji.resource().name()));
+                                }
+                            }
+                        }
+                    }
+                }
+
+                for (shortcode, _) in utils::emoji::shortcodes() {
+                    completion.add(&format!(":{}:", shortcode));
+                }
+                Ok(())
+            },
+        )
+        .expect("Unable to hook discord emoji completion");
+
         Completions {
             _guild_completion_hook,
             _channel_completion_hook,
+            _thread_completion_hook,
             _dm_completion_hook,
+            _user_completion_hook,
+            _nick_completion_hook,
+            _channel_mention_completion_hook,
+            _role_completion_hook,
+            _emoji_completion_hook,
         }
     }
 }
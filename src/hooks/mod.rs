@@ -32,7 +32,7 @@ impl Hooks {
         let _command = command::hook(discord_connection.clone(), instance.clone(), config.clone());
         tracing::trace!("Command hooked");
 
-        let _completions = Completions::hook_all(discord_connection.clone());
+        let _completions = Completions::hook_all(discord_connection.clone(), instance.clone());
         tracing::trace!("Completions hooked");
 
         let _options = Options::hook_all(weechat, config.clone());
@@ -1,14 +1,15 @@
 use crate::{
     buffer::{channel::Channel, ext::BufferExt},
     config::Config,
-    discord::discord_connection::DiscordConnection,
+    discord::{discord_connection::DiscordConnection, presence},
     instance::Instance,
+    refcell::RefCell,
 };
-use once_cell::sync::Lazy;
 use std::{
     borrow::{Borrow, Cow},
-    sync::{Arc, Mutex},
-    time::{SystemTime, UNIX_EPOCH},
+    collections::HashMap,
+    rc::Rc,
+    time::{Duration, Instant},
 };
 use twilight_model::id::{
     marker::{ChannelMarker, GuildMarker},
@@ -19,20 +20,42 @@ use weechat::{
     ReturnCode, Weechat,
 };
 
+/// A pause in typing this long starts a new stretch of typing
+const TYPING_IDLE: Duration = Duration::from_secs(2);
+
+/// Typing in the input of a channel buffer
+struct InputTyping {
+    /// When the current stretch of typing started
+    started: Instant,
+    last_input: Instant,
+    last_sent: Option<Instant>,
+}
+
 pub struct Signals {
     _buffer_closing_hook: SignalHook,
     _buffer_switch_hook: SignalHook,
     _buffer_typing_hook: SignalHook,
+    _away_hooks: Vec<SignalHook>,
+    _nick_activity_hook: SignalHook,
 }
 
 impl Signals {
     pub fn hook_all(connection: DiscordConnection, instance: Instance, config: Config) -> Signals {
         let inner_connection = connection.clone();
+        let typing_channels: Rc<RefCell<HashMap<Id<ChannelMarker>, InputTyping>>> =
+            Rc::new(RefCell::new(HashMap::new()));
 
         let _buffer_closing_hook = SignalHook::new("buffer_closing", {
+            let connection = connection.clone();
+            let config = config.clone();
             let instance = instance.clone();
+            let typing_channels = Rc::clone(&typing_channels);
             move |_: &Weechat, _: &str, data: Option<SignalData>| {
                 if let Some(SignalData::Buffer(buffer)) = data {
+                    // A closed buffer is no longer away
+                    let buffer_name = buffer.full_name().to_string();
+                    Self::sync_away(&connection, &config, &instance, buffer_name, None);
+
                     if buffer.is_weecord_buffer() {
                         tracing::trace!(name = %buffer.full_name(), "Buffer close");
                         let guild_id = buffer.guild_id();
@@ -53,9 +76,8 @@ impl Signals {
                             },
                             Some("channel") => {
                                 if let Some(channel_id) = channel_id {
-                                    if let Some(buf) =
-                                        instance.borrow_channels_mut().remove(&channel_id)
-                                    {
+                                    typing_channels.borrow_mut().remove(&channel_id);
+                                    if let Some(buf) = instance.remove_channel(channel_id) {
                                         buf.set_closed();
                                     }
                                 } else {
@@ -71,12 +93,21 @@ impl Signals {
                                     },
                                 };
 
-                                if let Some(buf) =
-                                    instance.borrow_pins_mut().remove(&(guild_id, channel_id))
-                                {
+                                if let Some(buf) = instance.remove_pins(guild_id, channel_id) {
                                     buf.set_closed();
                                 }
                             },
+                            Some("forum") => {
+                                if let Some(channel_id) = channel_id {
+                                    if let Some(buf) =
+                                        instance.borrow_forums_mut().remove(&channel_id)
+                                    {
+                                        buf.set_closed();
+                                    }
+                                } else {
+                                    tracing::warn!("forum type buffer has no channel id");
+                                }
+                            },
                             _ => {},
                         };
                     }
@@ -172,43 +203,99 @@ impl Signals {
         })
         .expect("Unable to hook buffer_switch signal");
 
+        // Weechat has no away signal, instead plugins (like irc) set the `away` localvar
+        let _away_hooks = [
+            "buffer_localvar_added",
+            "buffer_localvar_changed",
+            "buffer_localvar_removed",
+        ]
+        .iter()
+        .map(|signal| {
+            SignalHook::new(signal, {
+                let connection = connection.clone();
+                let config = config.clone();
+                let instance = instance.clone();
+                move |_: &Weechat, _: &str, data: Option<SignalData>| {
+                    if let Some(SignalData::Buffer(buffer)) = data {
+                        Self::sync_away(
+                            &connection,
+                            &config,
+                            &instance,
+                            buffer.full_name().to_string(),
+                            buffer
+                                .get_localvar("away")
+                                .map(|message| message.to_string()),
+                        );
+                    }
+                    ReturnCode::Ok
+                }
+            })
+            .expect("Unable to hook buffer localvar signal")
+        })
+        .collect();
+
+        let _nick_activity_hook = SignalHook::new(
+            "input_text_changed",
+            |_: &Weechat, _: &str, _: Option<SignalData>| {
+                Weechat::bar_item_update("discord_nick_activity");
+                ReturnCode::Ok
+            },
+        )
+        .expect("Unable to hook input_text_changed signal");
+
         let _buffer_typing_hook = SignalHook::new(
             "input_text_changed",
             move |_: &Weechat, _: &str, data: Option<SignalData>| {
-                static LAST_TYPING_TIMESTAMP: Lazy<Arc<Mutex<u64>>> =
-                    Lazy::new(|| Arc::new(Mutex::new(0)));
+                if let Some(SignalData::Buffer(buffer)) = data {
+                    let channel_id = match buffer.channel_id() {
+                        Some(channel_id) => channel_id,
+                        None => return ReturnCode::Ok,
+                    };
 
-                if !config.send_typing() {
-                    return ReturnCode::Ok;
-                }
+                    // Clearing the input, which includes sending the message, ends typing
+                    let input = buffer.input();
+                    if input.is_empty() || input.starts_with('/') {
+                        typing_channels.borrow_mut().remove(&channel_id);
+                        return ReturnCode::Ok;
+                    }
 
-                if let Some(SignalData::Buffer(buffer)) = data {
-                    if buffer.input().len() < 2 || buffer.input().starts_with('/') {
+                    if !config.send_typing_in(buffer.guild_id()) {
                         return ReturnCode::Ok;
                     }
 
-                    // TODO: Wait for user to type for 3 seconds
-                    let now = SystemTime::now();
-                    let timestamp_now = now
-                        .duration_since(UNIX_EPOCH)
-                        .expect("Time went backwards")
-                        .as_secs() as u64;
-
-                    if *LAST_TYPING_TIMESTAMP.lock().unwrap() + 9 < timestamp_now {
-                        *LAST_TYPING_TIMESTAMP.lock().unwrap() = timestamp_now;
-
-                        if let Some(channel_id) = buffer.channel_id() {
-                            if let Some(conn) = connection.borrow().as_ref() {
-                                let http = conn.http.clone();
-                                conn.rt.spawn(async move {
-                                    tracing::trace!(?channel_id, "Sending typing event");
-                                    if let Err(e) =
-                                        http.create_typing_trigger(channel_id).exec().await
-                                    {
-                                        tracing::error!("Sending typing start failed: {:#?}", e);
-                                    };
-                                });
-                            }
+                    let delay = Duration::from_secs(config.typing_delay() as u64);
+                    let interval = Duration::from_secs(config.typing_interval() as u64);
+                    let now = Instant::now();
+                    let send = {
+                        let mut typing_channels = typing_channels.borrow_mut();
+                        let typing = typing_channels.entry(channel_id).or_insert(InputTyping {
+                            started: now,
+                            last_input: now,
+                            last_sent: None,
+                        });
+                        if now - typing.last_input > TYPING_IDLE {
+                            typing.started = now;
+                        }
+                        typing.last_input = now;
+
+                        let sustained = now - typing.started >= delay;
+                        let due = typing.last_sent.map_or(true, |sent| now - sent >= interval);
+                        if sustained && due {
+                            typing.last_sent = Some(now);
+                        }
+                        sustained && due
+                    };
+
+                    if send {
+                        if let Some(conn) = connection.borrow().as_ref() {
+                            let http = conn.http.clone();
+                            conn.rt.spawn(async move {
+                                tracing::trace!(?channel_id, "Sending typing event");
+                                if let Err(e) = http.create_typing_trigger(channel_id).exec().await
+                                {
+                                    tracing::error!("Sending typing start failed: {:#?}", e);
+                                };
+                            });
                         }
                     }
                 }
@@ -221,9 +308,53 @@ impl Signals {
             _buffer_closing_hook,
             _buffer_switch_hook,
             _buffer_typing_hook,
+            _away_hooks,
+            _nick_activity_hook,
         }
     }
 
+    /// Set the away status while any buffer is away, and restore the configured one when none are
+    fn sync_away(
+        connection: &DiscordConnection,
+        config: &Config,
+        instance: &Instance,
+        buffer_name: String,
+        away_message: Option<String>,
+    ) {
+        let away_status = match config.sync_away() {
+            Some(away_status) => away_status,
+            None => return,
+        };
+
+        let presence = match away_message {
+            Some(message) => {
+                // Other localvars of an away buffer changing should not resend the presence
+                let previous = instance
+                    .borrow_away_buffers_mut()
+                    .insert(buffer_name, message.clone());
+                if previous.as_ref() == Some(&message) {
+                    return;
+                }
+                presence::away_presence(away_status, message)
+            },
+            None => {
+                let mut away_buffers = instance.borrow_away_buffers_mut();
+                if away_buffers.remove(&buffer_name).is_none() || !away_buffers.is_empty() {
+                    return;
+                }
+                presence::presence(config.status(), config.custom_status().as_ref())
+            },
+        };
+
+        let connection = connection.clone();
+        Weechat::spawn(async move {
+            if let Err(e) = connection.update_presence(presence).await {
+                tracing::warn!("Unable to sync away status: {}", e);
+            }
+        })
+        .detach();
+    }
+
     async fn ack(
         guild_id: Option<Id<GuildMarker>>,
         channel_id: Id<ChannelMarker>,
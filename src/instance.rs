@@ -1,5 +1,5 @@
 use crate::{
-    buffer::{channel::Channel, guild::Guild, pins::Pins},
+    buffer::{channel::Channel, forum::Forum, guild::Guild, pins::Pins},
     discord::typing_indicator::TypingTracker,
     twilight_utils::MemberList,
 };
@@ -13,13 +13,31 @@ use twilight_model::id::{
     Id,
 };
 
+/// The open buffers of a channel, which gateway events for it are dispatched to
+#[derive(Clone, Default)]
+pub struct ChannelRoute {
+    pub channel: Option<Channel>,
+    pub pins: Option<Pins>,
+}
+
+impl ChannelRoute {
+    fn is_empty(&self) -> bool {
+        self.channel.is_none() && self.pins.is_none()
+    }
+}
+
 #[derive(Clone)]
 pub struct Instance {
     guilds: Rc<RwLock<HashMap<Id<GuildMarker>, Guild>>>,
     channels: Rc<RwLock<HashMap<Id<ChannelMarker>, Channel>>>,
     private_channels: Rc<RwLock<HashMap<Id<ChannelMarker>, Channel>>>,
     pins: Rc<RwLock<HashMap<(Option<Id<GuildMarker>>, Id<ChannelMarker>), Pins>>>,
+    forums: Rc<RwLock<HashMap<Id<ChannelMarker>, Forum>>>,
+    // Guild, thread and private channels along with pins, indexed by channel id alone
+    routes: Rc<RwLock<HashMap<Id<ChannelMarker>, ChannelRoute>>>,
     typing_tracker: Rc<RwLock<TypingTracker>>,
+    /// Away messages of weechat buffers, by buffer name
+    away_buffers: Rc<RwLock<HashMap<String, String>>>,
     member_lists: Rc<RwLock<HashMap<Id<GuildMarker>, MemberList>>>,
 }
 
@@ -30,7 +48,10 @@ impl Instance {
             channels: Rc::new(RwLock::new(HashMap::new())),
             private_channels: Rc::new(RwLock::new(HashMap::new())),
             pins: Rc::new(RwLock::new(HashMap::new())),
+            forums: Rc::new(RwLock::new(HashMap::new())),
+            routes: Rc::new(RwLock::new(HashMap::new())),
             typing_tracker: Rc::new(RwLock::new(TypingTracker::new())),
+            away_buffers: Rc::new(RwLock::new(HashMap::new())),
             member_lists: Rc::new(RwLock::new(HashMap::new())),
         }
     }
@@ -58,12 +79,6 @@ impl Instance {
         self.channels.read()
     }
 
-    pub fn borrow_channels_mut(
-        &self,
-    ) -> RwLockWriteGuard<'_, RawRwLock, HashMap<Id<ChannelMarker>, Channel>> {
-        self.channels.write()
-    }
-
     pub fn borrow_private_channels(
         &self,
     ) -> RwLockReadGuard<'_, RawRwLock, HashMap<Id<ChannelMarker>, Channel>> {
@@ -83,24 +98,150 @@ impl Instance {
         self.pins.write()
     }
 
+    /// Insert a guild channel or thread, returning the channel it replaced
+    pub fn insert_channel(
+        &self,
+        channel_id: Id<ChannelMarker>,
+        channel: Channel,
+    ) -> Option<Channel> {
+        let _routed = self
+            .routes
+            .write()
+            .entry(channel_id)
+            .or_default()
+            .channel
+            .replace(channel.clone());
+        self.channels.write().insert(channel_id, channel)
+    }
+
+    /// Insert a private channel, returning the channel it replaced
+    pub fn insert_private_channel(
+        &self,
+        channel_id: Id<ChannelMarker>,
+        channel: Channel,
+    ) -> Option<Channel> {
+        let _routed = self
+            .routes
+            .write()
+            .entry(channel_id)
+            .or_default()
+            .channel
+            .replace(channel.clone());
+        self.private_channels.write().insert(channel_id, channel)
+    }
+
+    /// Remove a guild or private channel
+    pub fn remove_channel(&self, channel_id: Id<ChannelMarker>) -> Option<Channel> {
+        let _routed = self.remove_route(channel_id, |route| route.channel.take());
+        let channel = self.channels.write().remove(&channel_id);
+        channel.or_else(|| self.private_channels.write().remove(&channel_id))
+    }
+
+    /// Insert a pins buffer, returning the one it replaced
+    pub fn insert_pins(&self, pins: Pins) -> Option<Pins> {
+        let channel_id = pins.channel_id;
+        let _routed = self
+            .routes
+            .write()
+            .entry(channel_id)
+            .or_default()
+            .pins
+            .replace(pins.clone());
+        self.pins.write().insert((pins.guild_id, channel_id), pins)
+    }
+
+    pub fn remove_pins(
+        &self,
+        guild_id: Option<Id<GuildMarker>>,
+        channel_id: Id<ChannelMarker>,
+    ) -> Option<Pins> {
+        let _routed = self.remove_route(channel_id, |route| route.pins.take());
+        self.pins.write().remove(&(guild_id, channel_id))
+    }
+
+    /// Take a buffer out of a route, dropping the route once it has no buffers left
+    fn remove_route<T>(
+        &self,
+        channel_id: Id<ChannelMarker>,
+        take: impl FnOnce(&mut ChannelRoute) -> Option<T>,
+    ) -> Option<T> {
+        let mut routes = self.routes.write();
+        let route = routes.get_mut(&channel_id)?;
+        let taken = take(route);
+        if route.is_empty() {
+            routes.remove(&channel_id);
+        }
+        taken
+    }
+
+    /// Remove every route, the buffers are dropped after the lock is released so their close
+    /// handlers don't deadlock
+    pub fn clear_routes(&self) {
+        let _routes: Vec<_> = self.routes.write().drain().collect();
+    }
+
+    pub fn route(&self, channel_id: Id<ChannelMarker>) -> Option<ChannelRoute> {
+        self.routes.read().get(&channel_id).cloned()
+    }
+
+    /// Run `f` with the open buffers of a channel, if there are any
+    ///
+    /// The route is cloned out of the index first, so `f` is free to open or close buffers
+    pub fn dispatch(&self, channel_id: Id<ChannelMarker>, f: impl FnOnce(&ChannelRoute)) {
+        if let Some(route) = self.route(channel_id) {
+            f(&route);
+        }
+    }
+
+    pub fn borrow_forums(
+        &self,
+    ) -> RwLockReadGuard<'_, RawRwLock, HashMap<Id<ChannelMarker>, Forum>> {
+        self.forums.read()
+    }
+
+    pub fn borrow_forums_mut(
+        &self,
+    ) -> RwLockWriteGuard<'_, RawRwLock, HashMap<Id<ChannelMarker>, Forum>> {
+        self.forums.write()
+    }
+
     pub fn borrow_typing_tracker_mut(&self) -> RwLockWriteGuard<'_, RawRwLock, TypingTracker> {
         self.typing_tracker.write()
     }
 
+    pub fn borrow_away_buffers_mut(
+        &self,
+    ) -> RwLockWriteGuard<'_, RawRwLock, HashMap<String, String>> {
+        self.away_buffers.write()
+    }
+
+    /// The away message of any away buffer, `None` if weechat is not away
+    pub fn away_message(&self) -> Option<String> {
+        self.away_buffers.read().values().next().cloned()
+    }
+
     pub fn search_buffer(
         &self,
         guild_id: Option<Id<GuildMarker>>,
         channel_id: Id<ChannelMarker>,
     ) -> Option<Channel> {
-        if let Some(guild_id) = guild_id {
-            if let Some(guild) = self.guilds.read().get(&guild_id) {
-                return guild.channels().get(&channel_id).cloned();
-            }
-        } else {
-            return self.private_channels.read().get(&channel_id).cloned();
-        }
+        self.route(channel_id)?
+            .channel
+            .filter(|channel| channel.guild_id() == guild_id)
+    }
 
-        None
+    /// Redraw every open channel buffer
+    pub fn redraw_all(&self) {
+        let channels: Vec<_> = self
+            .channels
+            .read()
+            .values()
+            .chain(self.private_channels.read().values())
+            .cloned()
+            .collect();
+        for channel in channels {
+            channel.redraw(&[]);
+        }
     }
 
     pub fn borrow_member_lists(
@@ -82,7 +82,11 @@ impl Plugin for Weecord {
                 let config = self.config.clone();
                 let instance = self.instance.clone();
                 async move {
-                    if let Ok(connection) = discord_connection.start(&token, tx).await {
+                    let presence = discord::presence::presence(
+                        config.status(),
+                        config.custom_status().as_ref(),
+                    );
+                    if let Ok(connection) = discord_connection.start(&token, presence, tx).await {
                         DiscordConnection::handle_events(rx, &connection, config, instance).await;
                     }
                 }
@@ -90,6 +94,11 @@ impl Plugin for Weecord {
             .detach();
         };
 
+        self.config.set_colors_changed_callback({
+            let instance = self.instance.clone();
+            move || instance.redraw_all()
+        });
+
         self.hooks.replace(hooks::Hooks::hook_all(
             weechat,
             self.discord_connection.clone(),
@@ -131,6 +140,9 @@ impl Drop for Weecord {
         // deadlock
         let mut pins: Vec<_> = self.instance.borrow_pins_mut().drain().collect();
         pins.clear();
+        self.instance.clear_routes();
+        let mut forums: Vec<_> = self.instance.borrow_forums_mut().drain().collect();
+        forums.clear();
         let mut channels: Vec<_> = self.instance.borrow_guilds_mut().drain().collect();
         channels.clear();
         let mut guilds: Vec<_> = self.instance.borrow_guilds_mut().drain().collect();
@@ -1,16 +1,18 @@
 use crate::{
+    config::Config,
     discord::discord_connection::ConnectionInner,
     twilight_utils::{ext::CachedMemberExt, Color, GroupIdExt},
 };
 use std::rc::Rc;
 use twilight_model::{
-    gateway::payload::incoming::MemberListItem,
+    gateway::{payload::incoming::MemberListItem, presence::Status},
     id::{marker::GuildMarker, Id},
 };
 use weechat::buffer::{BufferHandle, NickSettings};
 
 pub struct Nicklist {
     conn: ConnectionInner,
+    config: Config,
     guild_id: Option<Id<GuildMarker>>,
     handle: Rc<BufferHandle>,
 }
@@ -18,11 +20,13 @@ pub struct Nicklist {
 impl Nicklist {
     pub fn new(
         conn: &ConnectionInner,
+        config: &Config,
         guild_id: Option<Id<GuildMarker>>,
         handle: Rc<BufferHandle>,
     ) -> Nicklist {
         Nicklist {
             conn: conn.clone(),
+            config: config.clone(),
             guild_id,
             handle,
         }
@@ -82,13 +86,26 @@ impl Nicklist {
                             if let Some(guild_member) =
                                 self.conn.cache.member(guild_id, member.user.id)
                             {
-                                let color = guild_member
-                                    .color(&self.conn.cache)
-                                    .filter(|&c| c.0 != 0)
-                                    .map(|c| c.as_8bit().to_string());
+                                let status = self
+                                    .conn
+                                    .cache
+                                    .presence(guild_id, member.user.id)
+                                    .map_or(Status::Offline, |presence| presence.status());
+                                let color = if let Status::Offline | Status::Invisible = status {
+                                    Some(self.config.status_color(status))
+                                } else {
+                                    guild_member
+                                        .color(&self.conn.cache)
+                                        .filter(|&c| c.0 != 0)
+                                        .map(|c| c.as_8bit().to_string())
+                                };
 
                                 let display_name = guild_member.display_name(&self.conn.cache);
-                                let mut settings = NickSettings::new(&display_name);
+                                let prefix = self.config.status_prefix();
+                                let prefix_color = self.config.status_color(status);
+                                let mut settings = NickSettings::new(&display_name)
+                                    .set_prefix(&prefix)
+                                    .set_prefix_color(&prefix_color);
                                 if let Some(ref color) = color {
                                     settings = settings.set_color(color);
                                 }
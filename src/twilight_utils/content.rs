@@ -1,21 +1,31 @@
-use crate::twilight_utils::ext::{CachedMemberExt, ChannelExt};
+use crate::twilight_utils::ext::{ChannelExt, UserExt};
 use once_cell::sync::Lazy;
-use regex::Regex;
+use parsing::{Element, Markdown, MarkdownNode, Segment};
+use regex::{Captures, Regex};
+use std::{ops::Range, rc::Rc, sync::RwLock};
 use twilight_cache_inmemory::InMemoryCache;
 use twilight_mention::Mention;
-use twilight_model::id::{marker::GuildMarker, Id};
+use twilight_model::{
+    id::{
+        marker::{ChannelMarker, GuildMarker, UserMarker},
+        Id,
+    },
+    user::User,
+};
 
 pub fn create_mentions(
     cache: &InMemoryCache,
     guild_id: Option<Id<GuildMarker>>,
+    channel_id: Id<ChannelMarker>,
     input: &str,
-) -> String {
+) -> (String, Vec<AmbiguousMention>) {
     let mut out = create_channels(cache, guild_id, input);
-    out = create_users(cache, guild_id, &out);
-    out = create_roles(cache, guild_id, &out);
+    let (users, ambiguous) = create_users(cache, guild_id, channel_id, &out);
+    out = create_roles(cache, guild_id, &users);
+    out = create_unicode_emojis(cache, guild_id, &out);
     out = create_emojis(cache, guild_id, &out);
 
-    out
+    (out, ambiguous)
 }
 
 pub fn create_channels(
@@ -56,46 +66,240 @@ pub fn create_channels(
     out
 }
 
+/// An `@name` matching several members, left as is rather than guessing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AmbiguousMention {
+    pub name: String,
+    /// Tags of the matching users
+    pub candidates: Vec<String>,
+}
+
+/// Replace `@displayname`, `@username` and `@username#1234` with user mentions
+///
+/// In private channels the recipients are used instead of guild members
 pub fn create_users(
     cache: &InMemoryCache,
     guild_id: Option<Id<GuildMarker>>,
+    channel_id: Id<ChannelMarker>,
     input: &str,
-) -> String {
-    static USER_MENTION: Lazy<Regex> = Lazy::new(|| Regex::new(r"@(.{0,32}?)#(\d{2,4})").unwrap());
+) -> (String, Vec<AmbiguousMention>) {
+    static USER_MENTION: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"@([^\s@#<>:,`]{1,32})(?:#(\d{4}))?").unwrap());
+    // Punctuation which is more likely to end a sentence than a name
+    const TRAILING: &[char] = &['.', '!', '?', ';', ')', '\'', '"'];
 
-    let mut out = String::from(input);
+    if !input.contains('@') {
+        return (input.to_owned(), Vec::new());
+    }
 
-    let matches = USER_MENTION.captures_iter(input).collect::<Vec<_>>();
-    for user_match in matches {
+    let mut ambiguous = Vec::new();
+    let out = replace_in_text(input, &USER_MENTION, |user_match| {
+        let full_match = user_match.get(0).expect("group zero must exist").as_str();
         let user_name = user_match
             .get(1)
-            .expect("Regex contains exactly one group")
+            .expect("Regex contains at least one group")
             .as_str();
+        let discriminator = user_match.get(2).map(|d| d.as_str());
 
-        if let Some(guild_id) = guild_id {
-            for member in cache.iter().members().filter(|m| m.key().0 == guild_id) {
-                if let Some(nick) = member.nick() {
-                    if nick == user_name {
-                        out = out.replace(
-                            user_match.get(0).expect("group zero must exist").as_str(),
-                            &member.user_id().mention().to_string(),
+        let find = |name: &str, discriminator: Option<&str>| {
+            matching_users(cache, guild_id, channel_id, name, discriminator)
+        };
+        let mut candidates = find(user_name, discriminator);
+        let mut suffix = "";
+        if candidates.is_empty() && discriminator.is_none() {
+            let trimmed = user_name.trim_end_matches(TRAILING);
+            if !trimmed.is_empty() && trimmed != user_name {
+                candidates = find(trimmed, None);
+                suffix = &user_name[trimmed.len()..];
+            }
+        }
+
+        match candidates.as_slice() {
+            [] => full_match.to_owned(),
+            [(user_id, _)] => format!("{}{}", user_id.mention(), suffix),
+            _ => {
+                ambiguous.push(AmbiguousMention {
+                    name: full_match[..full_match.len() - suffix.len()].to_owned(),
+                    candidates: candidates.into_iter().map(|(_, tag)| tag).collect(),
+                });
+                full_match.to_owned()
+            },
+        }
+    });
+
+    (out, ambiguous)
+}
+
+/// Visit the users who can be mentioned in a channel, along with their nick
+fn for_each_mentionable_user(
+    cache: &InMemoryCache,
+    guild_id: Option<Id<GuildMarker>>,
+    channel_id: Id<ChannelMarker>,
+    mut f: impl FnMut(&User, Option<&str>),
+) {
+    match guild_id {
+        Some(guild_id) => {
+            if let Some(members) = cache.guild_members(guild_id) {
+                for &user_id in members.iter() {
+                    if let Some(user) = cache.user(user_id) {
+                        let member = cache.member(guild_id, user_id);
+                        f(
+                            user.value(),
+                            member.as_ref().and_then(|member| member.nick()),
                         );
                     }
                 }
-
-                if member.user(cache).expect("FIX ME").name == user_name {
-                    out = out.replace(
-                        user_match.get(0).expect("group zero must exist").as_str(),
-                        &member.user_id().mention().to_string(),
-                    );
+            }
+        },
+        None => {
+            if let Some(channel) = cache.channel(channel_id) {
+                for user in channel.recipients.iter().flatten() {
+                    f(user, None);
                 }
             }
+        },
+    }
+}
+
+/// Find the ids and tags of the users whose nick or username is `name`, ignoring case unless
+/// that is ambiguous
+fn matching_users(
+    cache: &InMemoryCache,
+    guild_id: Option<Id<GuildMarker>>,
+    channel_id: Id<ChannelMarker>,
+    name: &str,
+    discriminator: Option<&str>,
+) -> Vec<(Id<UserMarker>, String)> {
+    // (user id, user tag, whether the case matched exactly)
+    let mut matches = Vec::new();
+    for_each_mentionable_user(cache, guild_id, channel_id, |user, nick| {
+        if let Some(discriminator) = discriminator {
+            if format!("{:04}", user.discriminator) != discriminator {
+                return;
+            }
+        }
+        let names = std::iter::once(user.name.as_str()).chain(nick);
+        let mut exact = false;
+        let mut found = false;
+        for candidate in names {
+            if candidate == name {
+                exact = true;
+            }
+            if candidate.eq_ignore_ascii_case(name) {
+                found = true;
+            }
+        }
+        if found {
+            matches.push((user.id, user.tag(), exact));
         }
+    });
+
+    if matches.len() > 1 && matches.iter().filter(|(_, _, exact)| *exact).count() == 1 {
+        matches.retain(|(_, _, exact)| *exact);
     }
 
+    matches
+        .into_iter()
+        .map(|(user_id, tag, _)| (user_id, tag))
+        .collect()
+}
+
+/// Replace the matches of `pattern` which are parsed as markdown text, leaving the ones in code
+/// spans and blocks untouched
+///
+/// The matches are swapped for marker characters before parsing, the markers which end up in
+/// `Text` nodes tell which matches to replace
+fn replace_in_text(
+    input: &str,
+    pattern: &Regex,
+    mut replace: impl FnMut(&Captures) -> String,
+) -> String {
+    // A private use plane, the markdown parser takes its own placeholders from another one
+    const MARKERS: Range<u32> = 0x10_0000..0x10_FFFE;
+
+    // Markers could not be told apart from the message itself
+    if input.chars().any(|c| MARKERS.contains(&(c as u32))) {
+        return input.to_owned();
+    }
+    let matches = pattern
+        .captures_iter(input)
+        .take((MARKERS.end - MARKERS.start) as usize)
+        .collect::<Vec<_>>();
+    if matches.is_empty() {
+        return input.to_owned();
+    }
+
+    let mut marked = String::with_capacity(input.len());
+    let mut start = 0;
+    for (i, caps) in matches.iter().enumerate() {
+        let full_match = caps.get(0).expect("group zero must exist");
+        marked.push_str(&input[start..full_match.start()]);
+        marked.push(
+            std::char::from_u32(MARKERS.start + i as u32).expect("markers are valid characters"),
+        );
+        start = full_match.end();
+    }
+    marked.push_str(&input[start..]);
+
+    let markdown = parsing::parse_markdown(&marked);
+    let mut in_text = vec![false; matches.len()];
+    visit_text(&markdown, &markdown.nodes, &mut |c| {
+        if let Some(index) = (c as u32).checked_sub(MARKERS.start) {
+            if let Some(in_text) = in_text.get_mut(index as usize) {
+                *in_text = true;
+            }
+        }
+    });
+
+    let mut out = String::with_capacity(input.len());
+    let mut start = 0;
+    for (caps, in_text) in matches.iter().zip(in_text) {
+        let full_match = caps.get(0).expect("group zero must exist");
+        out.push_str(&input[start..full_match.start()]);
+        if in_text {
+            out.push_str(&replace(caps));
+        } else {
+            out.push_str(full_match.as_str());
+        }
+        start = full_match.end();
+    }
+    out.push_str(&input[start..]);
+
     out
 }
 
+/// Visit the characters of the `Text` nodes, including the ones in the text of masked links
+fn visit_text(
+    markdown: &Markdown,
+    nodes: &[Rc<RwLock<MarkdownNode>>],
+    visit: &mut impl FnMut(char),
+) {
+    use MarkdownNode::*;
+    for node in nodes {
+        match &*node.read().unwrap() {
+            Text(text) => {
+                for segment in markdown.segments(text) {
+                    match segment {
+                        Segment::Text(text) => text.chars().for_each(&mut *visit),
+                        Segment::Element(Element::MaskedLink { text, .. }) => {
+                            visit_text(markdown, text, visit)
+                        },
+                        Segment::Element(_) => {},
+                    }
+                }
+            },
+            Bold(children)
+            | Italic(children)
+            | Underline(children)
+            | Strikethrough(children)
+            | Spoiler(children)
+            | BlockQuote(children)
+            | SingleBlockQuote(children) => visit_text(markdown, children, visit),
+            _ => {},
+        }
+    }
+}
+
 pub fn create_roles(
     cache: &InMemoryCache,
     guild_id: Option<Id<GuildMarker>>,
@@ -172,5 +376,178 @@ pub fn create_emojis(
     out
 }
 
+/// Replace shortcodes of unicode emojis, unless a custom emoji has the same name
+///
+/// Code spans and blocks, and the names inside custom emojis and timestamps, are left untouched
+pub fn create_unicode_emojis(
+    cache: &InMemoryCache,
+    guild_id: Option<Id<GuildMarker>>,
+    input: &str,
+) -> String {
+    static EMOJI_SHORTCODES: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"<a?:\w+:\d+>|<t:-?\d+(?::\w)?>|(\\?):(\w+):").unwrap());
+
+    replace_in_text(input, &EMOJI_SHORTCODES, |emoji_match| {
+        let full_match = emoji_match.get(0).expect("group zero must exist").as_str();
+        let (emoji_prefix, emoji_name) = match (emoji_match.get(1), emoji_match.get(2)) {
+            (Some(prefix), Some(name)) => (prefix.as_str(), name.as_str()),
+            // A custom emoji or timestamp
+            _ => return full_match.to_owned(),
+        };
+
+        let is_custom = guild_id.map_or(false, |guild_id| {
+            cache.iter().emojis().any(|emoji_ref| {
+                emoji_ref.guild_id() == guild_id
+                    && emoji_ref.value().resource().name() == emoji_name
+            })
+        });
+        if emoji_prefix == "\\" || is_custom {
+            return full_match.to_owned();
+        }
+
+        discord_emoji::lookup(emoji_name).map_or_else(|| full_match.to_owned(), ToOwned::to_owned)
+    })
+}
+
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::{create_unicode_emojis, create_users, AmbiguousMention};
+    use twilight_cache_inmemory::InMemoryCache;
+    use twilight_model::{
+        datetime::Timestamp, gateway::payload::incoming::MemberAdd, guild::Member, id::Id,
+        user::User,
+    };
+
+    fn add_member(
+        cache: &InMemoryCache,
+        id: u64,
+        name: &str,
+        discriminator: u16,
+        nick: Option<&str>,
+    ) {
+        cache.update(&MemberAdd(Member {
+            avatar: None,
+            deaf: false,
+            guild_id: Id::new(1),
+            joined_at: Timestamp::from_secs(1_632_072_645).expect("non zero"),
+            mute: false,
+            nick: nick.map(ToOwned::to_owned),
+            pending: false,
+            premium_since: None,
+            roles: vec![],
+            user: User {
+                accent_color: None,
+                avatar: None,
+                banner: None,
+                bot: false,
+                discriminator,
+                email: None,
+                flags: None,
+                id: Id::new(id),
+                locale: None,
+                mfa_enabled: None,
+                name: name.to_owned(),
+                premium_type: None,
+                public_flags: None,
+                system: None,
+                verified: None,
+            },
+            communication_disabled_until: None,
+        }));
+    }
+
+    fn cache() -> InMemoryCache {
+        let cache = InMemoryCache::new();
+        add_member(&cache, 1, "alice", 1, None);
+        add_member(&cache, 2, "Bob", 2, None);
+        add_member(&cache, 3, "bob", 3, None);
+        add_member(&cache, 4, "dave", 4, None);
+        add_member(&cache, 5, "dave", 5, None);
+        add_member(&cache, 6, "eve", 6, Some("Evie"));
+        cache
+    }
+
+    fn users(input: &str) -> (String, Vec<AmbiguousMention>) {
+        create_users(&cache(), Some(Id::new(1)), Id::new(1), input)
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(users("hi @alice").0, "hi <@1>");
+        assert_eq!(users("hi @ALICE").0, "hi <@1>");
+        assert_eq!(users("hi @Evie").0, "hi <@6>");
+        assert_eq!(users("hi @nobody").0, "hi @nobody");
+    }
+
+    #[test]
+    fn ambiguous() {
+        let (out, mut ambiguous) = users("hi @dave");
+        assert_eq!(out, "hi @dave");
+        ambiguous[0].candidates.sort();
+        assert_eq!(
+            ambiguous,
+            vec![AmbiguousMention {
+                name: "@dave".to_owned(),
+                candidates: vec!["dave#0004".to_owned(), "dave#0005".to_owned()],
+            }]
+        );
+
+        let (out, ambiguous) = users("hi @BOB");
+        assert_eq!(out, "hi @BOB");
+        assert_eq!(ambiguous.len(), 1);
+    }
+
+    #[test]
+    fn exact_case_tie_break() {
+        assert_eq!(users("@Bob @bob"), ("<@2> <@3>".to_owned(), Vec::new()));
+    }
+
+    #[test]
+    fn trailing_punctuation() {
+        assert_eq!(users("hi @alice.").0, "hi <@1>.");
+        assert_eq!(users("(@alice?)").0, "(<@1>?)");
+        assert_eq!(users("@dave!").1[0].name, "@dave");
+    }
+
+    #[test]
+    fn discriminator() {
+        assert_eq!(users("@dave#0005").0, "<@5>");
+        assert_eq!(users("@dave#0001").0, "@dave#0001");
+    }
+
+    #[test]
+    fn code_is_untouched() {
+        assert_eq!(
+            users("`@alice` @alice ```\n@alice\n```").0,
+            "`@alice` <@1> ```\n@alice\n```"
+        );
+    }
+
+    #[test]
+    fn unicode_emojis() {
+        let emojis = |input: &str| create_unicode_emojis(&InMemoryCache::new(), None, input);
+
+        assert_eq!(emojis("hi :fire:"), "hi 🔥");
+        assert_eq!(emojis("hi \\:fire:"), "hi \\:fire:");
+        assert_eq!(
+            emojis("<:fire:123> <a:fire:456>"),
+            "<:fire:123> <a:fire:456>"
+        );
+        assert_eq!(emojis("<t:1:R> <t:2>"), "<t:1:R> <t:2>");
+        assert_eq!(
+            emojis("[:fire:](https://example.com)"),
+            "[🔥](https://example.com)"
+        );
+    }
+
+    #[test]
+    fn unicode_emojis_skip_code() {
+        let emojis = |input: &str| create_unicode_emojis(&InMemoryCache::new(), None, input);
+
+        assert_eq!(emojis("`:fire:` :fire:"), "`:fire:` 🔥");
+        assert_eq!(
+            emojis("```\n:fire:\n``` :fire: ```:fire:```"),
+            "```\n:fire:\n``` 🔥 ```:fire:```"
+        );
+    }
+}
@@ -16,6 +16,7 @@ pub trait ChannelExt {
     fn can_send(&self, cache: &InMemoryCache) -> Option<bool>;
     fn has_permission(&self, cache: &InMemoryCache, permissions: Permissions) -> Option<bool>;
     fn is_text_channel(&self, cache: &InMemoryCache) -> bool;
+    fn is_thread(&self) -> bool;
     fn member_list_id(&self, cache: &InMemoryCache) -> MemberListId;
     fn member_has_permission(
         &self,
@@ -84,6 +85,15 @@ impl ChannelExt for Channel {
         }
     }
 
+    fn is_thread(&self) -> bool {
+        matches!(
+            self.kind,
+            ChannelType::GuildNewsThread
+                | ChannelType::GuildPublicThread
+                | ChannelType::GuildPrivateThread
+        )
+    }
+
     fn member_list_id(&self, cache: &InMemoryCache) -> MemberListId {
         match self.kind {
             ChannelType::Group | ChannelType::Private => MemberListId::Everyone,
@@ -1,8 +1,13 @@
 use crate::utils;
 use twilight_cache_inmemory::{model::CachedGuild, InMemoryCache};
 use twilight_model::{
-    channel::Channel,
-    id::{marker::GuildMarker, Id},
+    channel::{Channel, ChannelType},
+    guild::PremiumTier,
+    id::{
+        marker::{ChannelMarker, GuildMarker, UserMarker},
+        Id,
+    },
+    user::PremiumType,
 };
 
 mod color;
@@ -14,7 +19,7 @@ use crate::weechat2::StyledString;
 pub use color::*;
 pub use member_list::*;
 
-use self::ext::ChannelExt;
+use self::ext::{CachedMemberExt, ChannelExt, UserExt};
 
 pub fn search_cached_striped_guild_name(
     cache: &InMemoryCache,
@@ -54,7 +59,7 @@ pub fn search_cached_stripped_guild_channel_name(
         .expect("guild_channels never fails");
     for channel_id in channels.iter() {
         if let Some(channel) = cache.channel(*channel_id) {
-            if !channel.is_text_channel(cache) {
+            if !channel.is_text_channel(cache) || channel.is_thread() {
                 continue;
             }
             if utils::clean_name(&channel.name()) == utils::clean_name(target) {
@@ -67,6 +72,99 @@ pub fn search_cached_stripped_guild_channel_name(
     None
 }
 
+pub fn search_cached_stripped_forum_name(
+    cache: &InMemoryCache,
+    guild_id: Id<GuildMarker>,
+    target: &str,
+) -> Option<Channel> {
+    let channels = cache
+        .guild_channels(guild_id)
+        .expect("guild_channels never fails");
+    channels
+        .iter()
+        .flat_map(|channel_id| cache.channel(*channel_id))
+        .find(|channel| {
+            channel.kind == ChannelType::GuildForum
+                && utils::clean_name(&channel.name()) == utils::clean_name(target)
+        })
+        .map(|channel| channel.value().clone())
+}
+
+/// Active threads of a channel that are in the cache
+pub fn cached_channel_threads(
+    cache: &InMemoryCache,
+    guild_id: Id<GuildMarker>,
+    parent_id: Id<ChannelMarker>,
+) -> Vec<Channel> {
+    let channels = cache
+        .guild_channels(guild_id)
+        .expect("guild_channels never fails");
+    channels
+        .iter()
+        .flat_map(|channel_id| cache.channel(*channel_id))
+        .filter(|channel| channel.is_thread() && channel.parent_id == Some(parent_id))
+        .map(|channel| channel.value().clone())
+        .collect()
+}
+
+pub fn search_cached_stripped_thread_name(
+    cache: &InMemoryCache,
+    guild_id: Id<GuildMarker>,
+    parent_id: Id<ChannelMarker>,
+    target: &str,
+) -> Option<Channel> {
+    cached_channel_threads(cache, guild_id, parent_id)
+        .into_iter()
+        .find(|thread| utils::clean_name(&thread.name()) == utils::clean_name(target))
+}
+
+/// Find a cached guild member by their display name or username, ignoring case
+pub fn search_cached_member_name(
+    cache: &InMemoryCache,
+    guild_id: Id<GuildMarker>,
+    target: &str,
+) -> Option<Id<UserMarker>> {
+    cache
+        .guild_members(guild_id)?
+        .iter()
+        .copied()
+        .find(|&user_id| {
+            cache.member(guild_id, user_id).map_or(false, |member| {
+                member.display_name(cache).eq_ignore_ascii_case(target)
+                    || member
+                        .user(cache)
+                        .map_or(false, |user| user.name.eq_ignore_ascii_case(target))
+            })
+        })
+}
+
+/// Find a cached user by id, tag, or (in a guild) member name
+pub fn search_cached_user(
+    cache: &InMemoryCache,
+    guild_id: Option<Id<GuildMarker>>,
+    target: &str,
+) -> Option<Id<UserMarker>> {
+    let target = target.trim_start_matches('@');
+    if let Some(user_id) = target.parse().ok().and_then(Id::new_checked) {
+        return Some(user_id);
+    }
+
+    if let Some(user_id) =
+        guild_id.and_then(|guild_id| search_cached_member_name(cache, guild_id, target))
+    {
+        return Some(user_id);
+    }
+
+    cache
+        .iter()
+        .users()
+        .find(|user| {
+            utils::clean_name_with_case(&user.tag()) == target
+                || user.name.eq_ignore_ascii_case(target)
+        })
+        .map(|user| user.id)
+}
+
 pub fn current_user_nick(guild: &CachedGuild, cache: &InMemoryCache) -> StyledString {
     let current_user = cache
         .current_user()
@@ -80,3 +178,31 @@ pub fn current_user_nick(guild: &CachedGuild, cache: &InMemoryCache) -> StyledSt
         current_user.name.into()
     }
 }
+
+/// The largest file, in bytes, the current user is allowed to upload in the given guild (or DMs)
+pub fn max_upload_size(cache: &InMemoryCache, guild_id: Option<Id<GuildMarker>>) -> u64 {
+    const MIB: u64 = 1024 * 1024;
+
+    let user_limit = match cache.current_user().and_then(|user| user.premium_type) {
+        Some(PremiumType::Nitro) => 100 * MIB,
+        Some(PremiumType::NitroClassic) => 50 * MIB,
+        _ => 8 * MIB,
+    };
+
+    let guild_limit = match guild_id
+        .and_then(|guild_id| cache.guild(guild_id))
+        .map(|guild| guild.premium_tier())
+    {
+        Some(PremiumTier::Tier2) => 50 * MIB,
+        Some(PremiumTier::Tier3) => 100 * MIB,
+        _ => 8 * MIB,
+    };
+
+    user_limit.max(guild_limit)
+}
+
+/// The unix timestamp, in seconds, encoded in a snowflake id
+pub fn snowflake_timestamp(id: u64) -> i64 {
+    const DISCORD_EPOCH: u64 = 1_420_070_400_000;
+    (((id >> 22) + DISCORD_EPOCH) / 1000) as i64
+}
@@ -5,9 +5,10 @@ use crate::{
 };
 use twilight_cache_inmemory::{model::CachedMember, InMemoryCache};
 
+/// Color `text`, an empty or "default" color leaves it unstyled
 pub fn colorize_string(text: &str, color: &str) -> StyledString {
     let mut builder = StyledString::new();
-    if text.is_empty() || color.is_empty() {
+    if text.is_empty() || color.is_empty() || color == "default" {
         builder.push_str(text);
     } else {
         builder.push_styled_str(Style::color(color), text);
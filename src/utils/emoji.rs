@@ -0,0 +1,113 @@
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use std::{borrow::Cow, collections::HashMap};
+
+const VARIATION_SELECTOR: char = '\u{FE0F}';
+
+/// How unicode emojis are rendered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmojiStyle {
+    Unicode,
+    Shortcode,
+    /// The emoji followed by its shortcode
+    Both,
+}
+
+impl EmojiStyle {
+    pub fn parse(style: &str) -> Option<Self> {
+        match style {
+            "unicode" => Some(Self::Unicode),
+            "shortcode" => Some(Self::Shortcode),
+            "both" => Some(Self::Both),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Unicode => "unicode",
+            Self::Shortcode => "shortcode",
+            Self::Both => "both",
+        }
+    }
+}
+
+/// Unicode emoji known to Discord, as `(shortcode, emoji)` pairs
+pub fn shortcodes() -> impl Iterator<Item = (&'static str, &'static str)> {
+    discord_emoji::EMOJI_MAP
+        .entries()
+        .map(|(shortcode, emoji)| (*shortcode, *emoji))
+}
+
+/// The shortest shortcode of each emoji, keyed without a trailing variation selector
+static SHORTCODES_BY_EMOJI: Lazy<HashMap<&'static str, &'static str>> = Lazy::new(|| {
+    let mut by_emoji: HashMap<&'static str, &'static str> = HashMap::new();
+    for (shortcode, emoji) in shortcodes() {
+        let emoji = emoji.trim_end_matches(VARIATION_SELECTOR);
+        let current = by_emoji.entry(emoji).or_insert(shortcode);
+        if (shortcode.len(), shortcode) < (current.len(), *current) {
+            *current = shortcode;
+        }
+    }
+    by_emoji
+});
+
+static EMOJI_REGEX: Lazy<Regex> = Lazy::new(|| {
+    let mut emojis = SHORTCODES_BY_EMOJI.keys().copied().collect::<Vec<_>>();
+    // Longest first so sequences are preferred over the emojis they are made of
+    emojis.sort_by(|a, b| b.len().cmp(&a.len()));
+    let alternatives = emojis
+        .into_iter()
+        .map(regex::escape)
+        .collect::<Vec<_>>()
+        .join("|");
+    Regex::new(&format!("(?:{}){}?", alternatives, VARIATION_SELECTOR)).unwrap()
+});
+
+/// Find the shortcode of a unicode emoji
+pub fn shortcode(emoji: &str) -> Option<&'static str> {
+    SHORTCODES_BY_EMOJI
+        .get(emoji.trim_end_matches(VARIATION_SELECTOR))
+        .copied()
+}
+
+/// Render the unicode emojis in `text` with the given style
+pub fn style_emojis(text: &str, style: EmojiStyle) -> Cow<'_, str> {
+    if style == EmojiStyle::Unicode || text.is_ascii() {
+        return Cow::Borrowed(text);
+    }
+
+    EMOJI_REGEX.replace_all(text, |emoji_match: &Captures| {
+        let emoji = emoji_match.get(0).expect("group zero must exist").as_str();
+        match (shortcode(emoji), style) {
+            (Some(shortcode), EmojiStyle::Shortcode) => format!(":{}:", shortcode),
+            (Some(shortcode), EmojiStyle::Both) => format!("{} :{}:", emoji, shortcode),
+            _ => emoji.to_owned(),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{shortcode, style_emojis, EmojiStyle};
+
+    #[test]
+    fn shortcode_lookup() {
+        assert_eq!(shortcode("🔥"), Some("fire"));
+        assert_eq!(shortcode("❤"), Some("heart"));
+        assert_eq!(shortcode("❤\u{FE0F}"), Some("heart"));
+        assert_eq!(shortcode("a"), None);
+    }
+
+    #[test]
+    fn styles() {
+        assert_eq!(style_emojis("hi 🔥", EmojiStyle::Unicode), "hi 🔥");
+        assert_eq!(style_emojis("hi 🔥", EmojiStyle::Shortcode), "hi :fire:");
+        assert_eq!(style_emojis("hi 🔥", EmojiStyle::Both), "hi 🔥 :fire:");
+        assert_eq!(
+            style_emojis("❤\u{FE0F}!", EmojiStyle::Shortcode),
+            ":heart:!"
+        );
+        assert_eq!(style_emojis("no emoji", EmojiStyle::Shortcode), "no emoji");
+    }
+}
@@ -1,10 +1,18 @@
 use crate::{
-    twilight_utils::{ext::ChannelExt, Color},
+    config::ColorConfig,
+    twilight_utils::{
+        ext::{CachedMemberExt, ChannelExt},
+        Color,
+    },
+    utils::{
+        color::colorize_string,
+        emoji::{style_emojis, EmojiStyle},
+    },
     weechat2::{Style, StyledString},
 };
 use chrono::{DateTime, Local, Offset};
 use itertools::{Itertools, Position};
-use parsing::MarkdownNode;
+use parsing::{Element, Line, Markdown, MarkdownNode, Segment};
 use std::{rc::Rc, sync::RwLock};
 use time::{macros::format_description, Duration, OffsetDateTime};
 use twilight_cache_inmemory::InMemoryCache;
@@ -18,40 +26,177 @@ struct FormattingState<'a> {
     guild_id: Option<Id<GuildMarker>>,
     show_unknown_ids: bool,
     show_formatting_chars: bool,
+    emoji_style: EmojiStyle,
+    colors: &'a ColorConfig,
+    spoiler_mask: Option<&'a str>,
     unknown_members: &'a mut Vec<Id<UserMarker>>,
+    markdown: &'a Markdown<'a>,
 }
 
+/// Spoilers are hidden behind `spoiler_mask` unless it is `None`
+#[allow(clippy::too_many_arguments)]
 pub fn discord_to_weechat(
     msg: &str,
     cache: &InMemoryCache,
     guild_id: Option<Id<GuildMarker>>,
     show_formatting_chars: bool,
     show_unknown_ids: bool,
+    emoji_style: EmojiStyle,
+    colors: &ColorConfig,
+    spoiler_mask: Option<&str>,
     unknown_members: &mut Vec<Id<UserMarker>>,
 ) -> StyledString {
+    let markdown = parsing::parse_markdown(msg);
     let mut state = FormattingState {
         cache,
         guild_id,
         show_unknown_ids,
         show_formatting_chars,
+        emoji_style,
+        colors,
+        spoiler_mask,
         unknown_members,
+        markdown: &markdown,
     };
-    let ast = parsing::parse_markdown(msg);
 
-    collect_children(&ast.0, &mut state)
+    collect_children(&markdown.nodes, &mut state)
+}
+
+/// Formatted nodes, with the line element the current line started with
+#[derive(Default)]
+struct Lines<'a> {
+    out: StyledString,
+    line: Option<(&'a Line<'a>, StyledString)>,
+}
+
+impl<'a> Lines<'a> {
+    fn push(&mut self, styled: StyledString) {
+        match &mut self.line {
+            Some((_, line)) => line.append(styled),
+            None => self.out.append(styled),
+        };
+    }
+
+    fn end_line(&mut self, state: &FormattingState) {
+        if let Some((line, content)) = self.line.take() {
+            self.out.append(format_line(line, content, state));
+        }
+    }
 }
 
 fn collect_children(
     styles: &[Rc<RwLock<MarkdownNode>>],
     state: &mut FormattingState,
 ) -> StyledString {
-    styles
-        .iter()
-        .map(|s| discord_to_weechat_reducer(&*s.read().unwrap(), state))
-        .fold(StyledString::new(), |mut acc, x| {
-            acc.append(x);
-            acc
-        })
+    let markdown = state.markdown;
+    let mut lines = Lines::default();
+
+    for style in styles {
+        match &*style.read().unwrap() {
+            // Headings, list items and subtext style the rest of their line, which may be several nodes
+            MarkdownNode::Text(text) => {
+                for segment in markdown.segments(text) {
+                    match segment {
+                        Segment::Text(text) => {
+                            for (i, line) in text.split('\n').enumerate() {
+                                if i > 0 {
+                                    lines.end_line(state);
+                                    lines.push("\n".into());
+                                }
+                                lines.push(
+                                    style_emojis(line, state.emoji_style).into_owned().into(),
+                                );
+                            }
+                        },
+                        Segment::Element(Element::Line(line)) => {
+                            lines.end_line(state);
+                            lines.line = Some((line, StyledString::new()));
+                        },
+                        Segment::Element(element) => lines.push(format_element(element, state)),
+                    }
+                }
+            },
+            node => lines.push(discord_to_weechat_reducer(node, state)),
+        }
+    }
+
+    lines.end_line(state);
+    lines.out
+}
+
+fn format_line(line: &Line, content: StyledString, state: &FormattingState) -> StyledString {
+    let show_fmt = state.show_formatting_chars;
+    let mut out = StyledString::new();
+
+    match *line {
+        Line::Heading(level) => {
+            out.push_style(Style::Bold)
+                .if_do(level == 1, |s| s.push_style(Style::Underline))
+                .if_do(show_fmt, |s| s.push_str(&"#".repeat(level)).push_str(" "))
+                .absorb(content)
+                .if_do(level == 1, |s| s.pop_style(Style::Underline))
+                .pop_style(Style::Bold);
+        },
+        Line::ListItem { indent, number } => {
+            out.push_str(&" ".repeat(indent));
+            match number {
+                Some(number) => out.push_str(number).push_str(". "),
+                None => out.push_str("• "),
+            };
+            out.append(content);
+        },
+        Line::Subtext => {
+            out.push_color(&state.colors.subtext)
+                .if_do(show_fmt, |s| s.push_str("-# "))
+                .absorb(content)
+                .pop_color(&state.colors.subtext);
+        },
+    }
+    out
+}
+
+/// Format the text of a `Text` node, expanding the elements it contains
+fn format_text(text: &str, state: &mut FormattingState) -> StyledString {
+    let markdown = state.markdown;
+    let mut out = StyledString::new();
+
+    for segment in markdown.segments(text) {
+        match segment {
+            Segment::Text(text) => {
+                out.push_str(&style_emojis(text, state.emoji_style));
+            },
+            Segment::Element(element) => out.append(format_element(element, state)),
+        }
+    }
+    out
+}
+
+fn format_element(element: &Element, state: &mut FormattingState) -> StyledString {
+    let show_fmt = state.show_formatting_chars;
+    let mut out = StyledString::new();
+
+    match element {
+        // Rendered as the text followed by the url, so the url can still be opened
+        Element::MaskedLink { text, url } => {
+            out.push_style(Style::Underline)
+                .absorb(collect_children(text, state))
+                .pop_style(Style::Underline)
+                .push_str(" (")
+                .push_str(url)
+                .push_str(")");
+        },
+        Element::SuppressedLink(url) => {
+            out.if_do(show_fmt, |s| s.push_str("<"))
+                .push_str(url)
+                .if_do(show_fmt, |s| s.push_str(">"));
+        },
+        Element::Literal(c) => {
+            out.push_str(&c.to_string());
+        },
+        // Split out by `collect_children`
+        Element::Line(_) => {},
+    }
+    out
 }
 
 trait Magic {
@@ -60,6 +205,10 @@ trait Magic {
         condition: bool,
         f: F,
     ) -> &'a mut Self;
+
+    /// Push a configured color, "default" keeps the surrounding color
+    fn push_color(&mut self, color: &str) -> &mut Self;
+    fn pop_color(&mut self, color: &str) -> &mut Self;
 }
 
 impl Magic for StyledString {
@@ -74,14 +223,38 @@ impl Magic for StyledString {
             self
         }
     }
+
+    fn push_color(&mut self, color: &str) -> &mut Self {
+        if color == "default" {
+            self
+        } else {
+            self.push_style(Style::color(color))
+        }
+    }
+
+    fn pop_color(&mut self, color: &str) -> &mut Self {
+        if color == "default" {
+            self
+        } else {
+            self.pop_style(Style::color(color))
+        }
+    }
+}
+
+/// Whether a message is entirely italic, which is how `/me` actions are sent
+pub fn is_action(msg: &str) -> bool {
+    let markdown = parsing::parse_markdown(msg);
+    match markdown.nodes.as_slice() {
+        [node] => matches!(&*node.read().unwrap(), MarkdownNode::Italic(_)),
+        _ => false,
+    }
 }
 
-// TODO: if the whole line is wrapped in *, render as CTCP ACTION rather than
-//       as fully italicized message.
 #[allow(clippy::too_many_lines)]
 fn discord_to_weechat_reducer(node: &MarkdownNode, state: &mut FormattingState) -> StyledString {
     use MarkdownNode::*;
     let show_fmt = state.show_formatting_chars;
+    let colors = state.colors;
     let mut out = StyledString::new();
 
     match node {
@@ -110,33 +283,50 @@ fn discord_to_weechat_reducer(node: &MarkdownNode, state: &mut FormattingState)
             out
         },
         Strikethrough(children) => {
-            out.push_style(Style::Color("red".into()))
+            out.push_color(&colors.strikethrough)
                 .if_do(show_fmt, |s| s.push_str("~~"))
                 .absorb(collect_children(children, state))
                 .if_do(show_fmt, |s| s.push_str("~~"))
-                .pop_style(Style::Color("red".into()));
+                .pop_color(&colors.strikethrough);
             out
         },
         Spoiler(children) => {
-            out.push_style(Style::Italic)
-                .push_str("||")
-                .absorb(collect_children(children, state))
-                .push_str("||")
-                .pop_style(Style::Italic);
+            let content = collect_children(children, state);
+            out.push_color(&colors.spoiler);
+            match state.spoiler_mask {
+                Some(mask) => {
+                    let masked: String = content
+                        .text()
+                        .chars()
+                        .map(|c| if c == '\n' { "\n" } else { mask })
+                        .collect();
+                    out.if_do(show_fmt, |s| s.push_str("||"))
+                        .push_str(&masked)
+                        .if_do(show_fmt, |s| s.push_str("||"));
+                },
+                None => {
+                    out.push_style(Style::Italic)
+                        .if_do(show_fmt, |s| s.push_str("||"))
+                        .absorb(content)
+                        .if_do(show_fmt, |s| s.push_str("||"))
+                        .pop_style(Style::Italic);
+                },
+            }
+            out.pop_color(&colors.spoiler);
             out
         },
         Text(string) => {
-            out.push_str(string);
+            out.append(format_text(string, state));
             out
         },
         InlineCode(string) => {
-            out.push_style(Style::color("8"))
+            out.push_color(&colors.inline_code)
                 .push_style(Style::Bold)
                 .if_do(show_fmt, |s| s.push_str("`"))
                 .push_str(string)
                 .if_do(show_fmt, |s| s.push_str("`"))
                 .pop_style(Style::Bold)
-                .pop_style(Style::color("8"));
+                .pop_color(&colors.inline_code);
 
             out
         },
@@ -148,11 +338,11 @@ fn discord_to_weechat_reducer(node: &MarkdownNode, state: &mut FormattingState)
             out.push_style(Style::Reset)
                 .if_do(show_fmt, |s| s.push_str("```").push_str(language))
                 .push_str("\n")
-                .push_style(Style::color("8"))
+                .push_color(&colors.code_block)
                 .push_style(Style::Bold)
                 .push_str(&text)
                 .pop_style(Style::Bold)
-                .pop_style(Style::color("8"))
+                .pop_color(&colors.code_block)
                 .if_do(show_fmt, |s| s.push_str("\n```"))
                 .pop_style(Style::Reset);
             out
@@ -160,6 +350,7 @@ fn discord_to_weechat_reducer(node: &MarkdownNode, state: &mut FormattingState)
         BlockQuote(children) => {
             out.append(format_block_quote(
                 collect_children(children, state).lines().into_iter(),
+                colors,
             ));
             out
         },
@@ -169,13 +360,33 @@ fn discord_to_weechat_reducer(node: &MarkdownNode, state: &mut FormattingState)
                     .lines()
                     .into_iter()
                     .map(strip_leading_bracket),
+                colors,
             ));
             out
         },
         UserMention(id) => {
             let id = Id::new(*id);
 
-            let replacement = if let Some(guild_id) = state.guild_id {
+            let is_self = state
+                .cache
+                .current_user()
+                .map_or(false, |user| user.id == id);
+            let mention_color = if is_self && colors.self_mention != "default" {
+                &colors.self_mention
+            } else {
+                &colors.mention
+            };
+
+            let replacement = if mention_color != "default" {
+                let name = match state.guild_id {
+                    Some(guild_id) => state
+                        .cache
+                        .member(guild_id, id)
+                        .map(|member| member.display_name(state.cache)),
+                    None => state.cache.user(id).map(|user| user.name.clone()),
+                };
+                name.map(|name| colorize_string(&format!("@{}", name), mention_color))
+            } else if let Some(guild_id) = state.guild_id {
                 state.cache.member(guild_id, id).map(|member| {
                     crate::utils::color::colorize_discord_member(state.cache, &member, true)
                 })
@@ -192,9 +403,9 @@ fn discord_to_weechat_reducer(node: &MarkdownNode, state: &mut FormattingState)
                 state.unknown_members.push(id);
 
                 if state.show_unknown_ids {
-                    format!("@{}", id).into()
+                    colorize_string(&format!("@{}", id), &colors.unknown_user)
                 } else {
-                    "@unknown-user".to_owned().into()
+                    colorize_string("@unknown-user", &colors.unknown_user)
                 }
             };
             out.append(mention);
@@ -243,7 +454,7 @@ fn discord_to_weechat_reducer(node: &MarkdownNode, state: &mut FormattingState)
     }
 }
 
-fn fmt_timestamp(timestamp: i64, style: char) -> String {
+pub fn fmt_timestamp(timestamp: i64, style: char) -> String {
     // Temporary solution until `time` supports local offset in multirhreaded environments
     let local_timestamp: DateTime<Local> = chrono::Local::now();
 
@@ -422,12 +633,13 @@ fn strip_leading_bracket(line: StyledString) -> StyledString {
 
 pub fn fold_lines<S: Into<StyledString>>(
     lines: impl Iterator<Item = S>,
-    sep: &str,
+    sep: impl Into<StyledString>,
 ) -> StyledString {
+    let sep = sep.into();
     let mut out = StyledString::new();
     for line in lines.with_position() {
         let newlines = matches!(line, Position::First(_) | Position::Middle(_));
-        out.push_str(sep);
+        out.append(sep.clone());
         out.absorb(line.into_inner().into());
         if newlines {
             out.push_str("\n");
@@ -436,13 +648,17 @@ pub fn fold_lines<S: Into<StyledString>>(
     out
 }
 
-fn format_block_quote(lines: impl Iterator<Item = StyledString>) -> StyledString {
-    fold_lines(lines, "▎")
+fn format_block_quote(
+    lines: impl Iterator<Item = StyledString>,
+    colors: &ColorConfig,
+) -> StyledString {
+    fold_lines(lines, colorize_string("▎", &colors.quote_bar))
 }
 
 #[cfg(test)]
 mod tests {
-    use super::discord_to_weechat;
+    use super::{discord_to_weechat, is_action, EmojiStyle};
+    use crate::config::ColorConfig;
     use twilight_cache_inmemory::InMemoryCache;
     use twilight_model::{
         channel::{Channel, ChannelType},
@@ -462,7 +678,18 @@ mod tests {
         cache: &InMemoryCache,
         guild_id: Option<Id<GuildMarker>>,
     ) -> String {
-        discord_to_weechat(str, cache, guild_id, true, false, &mut Vec::new()).build()
+        discord_to_weechat(
+            str,
+            cache,
+            guild_id,
+            true,
+            false,
+            EmojiStyle::Unicode,
+            &ColorConfig::default(),
+            None,
+            &mut Vec::new(),
+        )
+        .build()
     }
 
     #[test]
@@ -482,6 +709,28 @@ mod tests {
         );
     }
 
+    #[test]
+    fn spoilers() {
+        let hidden = |str: &str, show_formatting_chars: bool| {
+            discord_to_weechat(
+                str,
+                &InMemoryCache::new(),
+                None,
+                show_formatting_chars,
+                false,
+                EmojiStyle::Unicode,
+                &ColorConfig::default(),
+                Some("#"),
+                &mut Vec::new(),
+            )
+            .build()
+        };
+
+        assert_eq!(hidden("a ||secret|| b", true), "a ||######|| b");
+        assert_eq!(hidden("a ||**bold**|| b", false), "a #### b");
+        assert_eq!(format("||secret||"), "italic||secret||-italic");
+    }
+
     #[test]
     fn smoke_test() {
         assert_eq!(
@@ -495,6 +744,101 @@ mod tests {
         );
     }
 
+    #[test]
+    fn actions() {
+        assert!(is_action("_waves_"));
+        assert!(is_action("*waves*"));
+        assert!(!is_action("_waves_ hello"));
+        assert!(!is_action("__underlined__"));
+        assert!(!is_action("**bold**"));
+    }
+
+    #[test]
+    fn headings() {
+        assert_eq!(format("# Title"), "boldunderline# Title-underline-bold");
+        assert_eq!(
+            format("## Sub _title_"),
+            "bold## Sub italic_title_-italic-bold"
+        );
+        assert_eq!(format("#channel"), "#channel");
+        assert!(!format("```\n# not a heading\n```").contains("underline"));
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(
+            format("- one\n  * two\n3. three"),
+            "• one\n  • two\n3. three"
+        );
+        assert_eq!(format("text\n- item"), "text\n• item");
+    }
+
+    #[test]
+    fn subtext() {
+        assert_eq!(format("-# small"), "8-# smallreset");
+    }
+
+    #[test]
+    fn masked_links() {
+        assert_eq!(
+            format("see [docs](https://example.com)!"),
+            "see underlinedocs-underline (https://example.com)!"
+        );
+        assert_eq!(
+            format("[**a**](<https://example.com>)"),
+            "underlinebold**a**-bold-underline (https://example.com)"
+        );
+        assert_eq!(format("[a](not a url)"), "[a](not a url)");
+        assert_eq!(
+            format("`[a](https://example.com)`"),
+            "8bold`[a](https://example.com)`-boldreset"
+        );
+    }
+
+    #[test]
+    fn suppressed_links() {
+        assert_eq!(
+            format("look <https://example.com>"),
+            "look <https://example.com>"
+        );
+    }
+
+    #[test]
+    fn styled_links() {
+        assert_eq!(
+            format("**see <https://example.com>**"),
+            "bold**see <https://example.com>**-bold"
+        );
+        assert_eq!(
+            format("## see [docs](https://example.com) _now_"),
+            "bold## see underlinedocs-underline (https://example.com) italic_now_-italic-bold"
+        );
+        assert_eq!(
+            format("||spoiler [a](https://example.com)||"),
+            "italic||spoiler underlinea-underline (https://example.com)||-italic"
+        );
+
+        let hidden = discord_to_weechat(
+            "||spoiler [a](https://example.com)||",
+            &InMemoryCache::new(),
+            None,
+            true,
+            false,
+            EmojiStyle::Unicode,
+            &ColorConfig::default(),
+            Some("#"),
+            &mut Vec::new(),
+        )
+        .build();
+        assert_eq!(
+            hidden,
+            format!(
+                "||{}||",
+                "#".repeat("spoiler a (https://example.com)".len())
+            )
+        );
+    }
+
     #[test]
     fn roles() {
         let cache = InMemoryCache::new();
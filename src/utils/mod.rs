@@ -1,11 +1,12 @@
 pub mod color;
+pub mod emoji;
 mod flag;
 mod format;
 #[cfg(feature = "images")]
 pub mod image;
 
 pub use flag::Flag;
-pub use format::{discord_to_weechat, fold_lines};
+pub use format::{discord_to_weechat, fmt_timestamp, fold_lines, is_action};
 
 #[macro_export]
 macro_rules! match_map {
@@ -4,7 +4,7 @@ use std::{
     collections::{HashSet, VecDeque},
     rc::Rc,
 };
-use weechat::buffer::BufferHandle;
+use weechat::buffer::{Buffer, BufferHandle, BufferLine};
 
 pub trait WeechatMessage<I, S> {
     /// Format the message into the prefix and body
@@ -12,6 +12,8 @@ pub trait WeechatMessage<I, S> {
     fn tags(&self, state: &mut S) -> HashSet<Cow<'static, str>>;
     fn timestamp(&self, state: &mut S) -> i64;
     fn id(&self, state: &mut S) -> I;
+    /// A tag unique to the message, added to each of its lines so they can be found again
+    fn line_tag(&self, state: &mut S) -> String;
 }
 
 pub struct MessageRenderer<M: WeechatMessage<I, S> + Clone, I: Eq, S> {
@@ -65,6 +67,13 @@ impl<M: WeechatMessage<I, S> + Clone, I: Eq, S> MessageRenderer<M, I, S> {
         *self.last_read_id.borrow_mut() = Some(id);
     }
 
+    pub fn last_read_id(&self) -> Option<I>
+    where
+        I: Clone,
+    {
+        self.last_read_id.borrow().clone()
+    }
+
     fn print_msg(&self, msg: &M, log: bool) {
         let buffer = self
             .buffer_handle
@@ -74,6 +83,7 @@ impl<M: WeechatMessage<I, S> + Clone, I: Eq, S> MessageRenderer<M, I, S> {
         let mut state = self.state.borrow_mut();
         let (prefix, suffix) = msg.render(&mut state);
         let mut tags = msg.tags(&mut state);
+        tags.insert(msg.line_tag(&mut state).into());
         if !log {
             tags.insert("no_log".into());
         }
@@ -173,6 +183,67 @@ impl<M: WeechatMessage<I, S> + Clone, I: Eq, S> MessageRenderer<M, I, S> {
         }
     }
 
+    /// Re-render stored messages over their lines, the first one which can't be stops the rest
+    /// in favour of a single redraw of the whole buffer
+    pub fn refresh_messages(&self, ids: &[I]) {
+        if !ids.iter().all(|id| self.rerender_lines(id)) {
+            self.redraw_buffer();
+        }
+    }
+
+    /// Replace the lines of a message in place, returns false if its lines are missing from the
+    /// buffer or their number or tags changed
+    fn rerender_lines(&self, id: &I) -> bool {
+        let (tag, tags, prefix, body) = {
+            let messages = self.messages.borrow();
+            let mut state = self.state.borrow_mut();
+            let msg = match messages.iter().find(|msg| &msg.id(&mut state) == id) {
+                Some(msg) => msg,
+                // Nothing to update
+                None => return true,
+            };
+            let (prefix, body) = msg.render(&mut state);
+            let tag = msg.line_tag(&mut state);
+            let mut tags: HashSet<String> = msg
+                .tags(&mut state)
+                .into_iter()
+                .map(Cow::into_owned)
+                .collect();
+            tags.insert(tag.clone());
+            (tag, tags, prefix, body)
+        };
+
+        let buffer = self
+            .buffer_handle
+            .upgrade()
+            .expect("message renderer outlived buffer");
+
+        let lines = message_lines(&buffer, &tag);
+        let new_lines: Vec<_> = body.split('\n').collect();
+        if lines.is_empty() || lines.len() != new_lines.len() {
+            return false;
+        }
+        // Only the content of lines is updated, changed tags need the message to be printed again
+        let tags_changed = lines.iter().any(|(_, line_tags)| {
+            let line_tags: HashSet<&str> = line_tags
+                .iter()
+                .map(String::as_str)
+                .filter(|&line_tag| line_tag != "no_log")
+                .collect();
+            line_tags.len() != tags.len()
+                || line_tags.iter().any(|&line_tag| !tags.contains(line_tag))
+        });
+        if tags_changed {
+            return false;
+        }
+
+        for ((line, _), message) in lines.iter().zip(new_lines) {
+            line.set_prefix(&prefix);
+            line.set_message(message);
+        }
+        true
+    }
+
     pub fn get_nth_message(&self, index: usize) -> Option<M> {
         self.messages.borrow().iter().nth(index).cloned()
     }
@@ -181,18 +252,32 @@ impl<M: WeechatMessage<I, S> + Clone, I: Eq, S> MessageRenderer<M, I, S> {
         self.messages.borrow().iter().rev().nth(index).cloned()
     }
 
+    /// Remove a stored message, its lines stay in the buffer until it is redrawn
     pub fn remove_msg(&self, id: &I) {
-        {
-            let mut state = self.state.borrow_mut();
-            let index = self
-                .messages
-                .borrow()
-                .iter()
-                .position(|it| &it.id(&mut state) == id);
-            if let Some(index) = index {
-                self.messages.borrow_mut().remove(index);
-            }
+        let mut state = self.state.borrow_mut();
+        let mut messages = self.messages.borrow_mut();
+        if let Some(index) = messages.iter().position(|it| &it.id(&mut state) == id) {
+            messages.remove(index);
         }
-        self.redraw_buffer();
     }
 }
+
+/// Find the lines of a message by its line tag, along with the tags of each line
+fn message_lines<'a>(buffer: &'a Buffer, tag: &str) -> Vec<(BufferLine<'a>, Vec<String>)> {
+    let has_tag =
+        |(_, tags): &(BufferLine, Vec<String>)| tags.iter().any(|line_tag| line_tag == tag);
+    // The lines of a message are contiguous, and recently changed messages are usually near
+    // the end of the buffer, so the search stops at the first line after them
+    let mut lines: Vec<_> = buffer
+        .lines()
+        .rev()
+        .map(|line| {
+            let tags = line.tags().iter().map(ToString::to_string).collect();
+            (line, tags)
+        })
+        .skip_while(|line| !has_tag(line))
+        .take_while(|line| has_tag(line))
+        .collect();
+    lines.reverse();
+    lines
+}
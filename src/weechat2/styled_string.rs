@@ -274,6 +274,19 @@ impl StyledString {
         out
     }
 
+    /// The text without any styling
+    pub fn text(&self) -> String {
+        let mut out = String::new();
+        for op in &self.ops {
+            match op {
+                Op::Literal(text) => out.push_str(text),
+                Op::Newline => out.push('\n'),
+                Op::PushStyle(_) | Op::PopStyle(_) => {},
+            }
+        }
+        out
+    }
+
     pub fn find(&self, substr: &str) -> Option<usize> {
         let mut offset = 0;
         for op in &self.ops {
@@ -354,6 +367,18 @@ mod test {
         assert_eq!(string.find("Hi"), Some(3));
     }
 
+    #[test]
+    fn text() {
+        let mut string = StyledString::new();
+        string
+            .push_str("Foo\n")
+            .push_style(Style::Bold)
+            .push_str("Bar")
+            .pop_style(Style::Bold);
+
+        assert_eq!(string.text(), "Foo\nBar");
+    }
+
     #[test]
     fn slice() {
         let mut string = StyledString::new();
@@ -1,11 +1,15 @@
 #[cfg(feature = "images")]
 use crate::utils::image::*;
 use crate::{
-    config::Config,
-    discord::discord_connection::ConnectionInner,
+    config::{ColorConfig, Config},
+    discord::{discord_connection::ConnectionInner, notification_settings::NotificationLevel},
     match_map,
     twilight_utils::ext::{MessageExt, ShallowUser},
-    utils::fold_lines,
+    utils::{
+        color::colorize_string,
+        emoji::{style_emojis, EmojiStyle},
+        fold_lines,
+    },
     weechat2::{MessageRenderer, Style, StyledString, WeechatMessage},
     RefCell,
 };
@@ -14,13 +18,15 @@ use image::DynamicImage;
 use rand::{thread_rng, Rng};
 use std::{
     borrow::Cow,
-    collections::{HashSet, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
     rc::Rc,
+    time::{Duration, Instant},
 };
 use time::OffsetDateTime;
 use twilight_cache_inmemory::InMemoryCache;
+use twilight_http::error::ErrorType as HttpErrorType;
 use twilight_model::{
-    channel::{Message as DiscordMessage, ReactionType},
+    channel::{message::MessageType, Message as DiscordMessage, ReactionType},
     gateway::payload::{incoming::MessageUpdate, outgoing::RequestGuildMembers},
     id::{
         marker::{ChannelMarker, GuildMarker, MessageMarker, UserMarker},
@@ -47,6 +53,8 @@ pub enum WeecordMessage {
     LocalEcho {
         guild_id: Option<Id<GuildMarker>>,
         content: String,
+        reply: Option<Box<DiscordMessage>>,
+        attachment: Option<String>,
         timestamp: i64,
         nonce: u64,
     },
@@ -77,10 +85,33 @@ impl WeecordMessage {
         }
     }
 
-    pub fn new_echo(guild_id: Option<Id<GuildMarker>>, content: String, nonce: u64) -> Self {
+    pub fn new_echo(
+        guild_id: Option<Id<GuildMarker>>,
+        content: String,
+        nonce: u64,
+        reply: Option<DiscordMessage>,
+    ) -> Self {
         Self::LocalEcho {
             guild_id,
             content,
+            reply: reply.map(Box::new),
+            attachment: None,
+            timestamp: OffsetDateTime::now_utc().unix_timestamp(),
+            nonce,
+        }
+    }
+
+    pub fn new_upload_echo(
+        guild_id: Option<Id<GuildMarker>>,
+        caption: String,
+        nonce: u64,
+        filename: String,
+    ) -> Self {
+        Self::LocalEcho {
+            guild_id,
+            content: caption,
+            reply: None,
+            attachment: Some(filename),
             timestamp: OffsetDateTime::now_utc().unix_timestamp(),
             nonce,
         }
@@ -95,22 +126,65 @@ impl WeecordMessage {
             WeecordMessage::Notification { id, .. } => Id::new(*id),
         }
     }
+
+    /// The id of the message this one replies to
+    fn reference_id(&self) -> Option<Id<MessageMarker>> {
+        match self {
+            WeecordMessage::Text(msg) => msg.reference.as_ref().and_then(|r| r.message_id),
+            #[cfg(feature = "images")]
+            WeecordMessage::Image { msg, .. } => msg.reference.as_ref().and_then(|r| r.message_id),
+            _ => None,
+        }
+    }
 }
 
 impl WeechatMessage<Id<MessageMarker>, State> for WeecordMessage {
     fn render(&self, state: &mut State) -> (String, String) {
         match self {
             WeecordMessage::LocalEcho {
-                guild_id, content, ..
+                guild_id,
+                content,
+                reply,
+                attachment,
+                ..
             } => {
-                let content = crate::utils::discord_to_weechat(
+                let spoiler_mask = state.spoiler_mask(self.id());
+                let mut content = crate::utils::discord_to_weechat(
                     content,
                     &state.conn.cache,
                     *guild_id,
                     state.config.show_formatting_chars(),
                     state.config.show_unknown_user_ids(),
+                    state.config.emoji_style(),
+                    &state.config.colors(),
+                    spoiler_mask.as_deref(),
                     &mut Vec::new(),
                 );
+                if let Some(attachment) = attachment {
+                    if !content.is_empty() {
+                        content.push_str("\n");
+                    }
+                    content.push_str(&format!("[uploading {}]", attachment));
+                }
+                let mut body = format!(
+                    "{}{}{}",
+                    Weechat::color(&state.config.colors().local_echo),
+                    content.build(),
+                    Weechat::color("resetcolor")
+                );
+                if let Some(reply) = reply {
+                    body = format!(
+                        "{}\n{}",
+                        format_reply_preview(
+                            &state.conn.cache,
+                            &state.config,
+                            reply,
+                            state.config.reply_ping(),
+                            spoiler_mask.as_deref(),
+                        ),
+                        body
+                    );
+                }
                 (
                     format_author(
                         &state.conn.cache,
@@ -119,29 +193,32 @@ impl WeechatMessage<Id<MessageMarker>, State> for WeecordMessage {
                         false,
                     )
                     .build(),
-                    format!(
-                        "{}{}{}",
-                        Weechat::color("244"),
-                        content.build(),
-                        Weechat::color("resetcolor")
-                    ),
+                    body,
+                )
+            },
+            WeecordMessage::Text(msg) => {
+                let spoiler_mask = state.spoiler_mask(msg.id);
+                render_msg(
+                    &state.conn.cache,
+                    &state.config,
+                    msg,
+                    false,
+                    spoiler_mask.as_deref(),
+                    &mut state.unknown_members,
+                    Some(&mut state.references),
                 )
             },
-            WeecordMessage::Text(msg) => render_msg(
-                &state.conn.cache,
-                &state.config,
-                msg,
-                false,
-                &mut state.unknown_members,
-            ),
             #[cfg(feature = "images")]
             WeecordMessage::Image { msg, images } => {
+                let spoiler_mask = state.spoiler_mask(msg.id);
                 let (prefix, mut body) = render_msg(
                     &state.conn.cache,
                     &state.config,
                     msg,
                     false,
+                    spoiler_mask.as_deref(),
                     &mut state.unknown_members,
+                    Some(&mut state.references),
                 );
 
                 if !images.is_empty() {
@@ -162,31 +239,65 @@ impl WeechatMessage<Id<MessageMarker>, State> for WeecordMessage {
 
         let mut discord_msg_tags = |msg: &DiscordMessage| {
             let cache = &state.conn.cache;
-            let private = cache.channel(msg.channel_id).is_some();
+            let private = msg.guild_id.is_none();
 
-            let mentioned = cache
-                .current_user()
-                .map(|user| msg.mentions.iter().any(|m| m.id == user.id))
-                .unwrap_or(false);
+            if is_action(msg) {
+                tags.insert("irc_action".into());
+            }
 
             let is_own = msg.is_own(&state.conn.cache);
 
             if is_own {
                 tags.insert("self_msg".into());
                 tags.insert("notify_none".into());
-            } else {
-                if mentioned {
-                    tags.insert("notify_highlight".into());
-                }
+                return;
+            }
 
-                if private {
-                    tags.insert("notify_private".into());
-                }
+            let local_override = msg.guild_id.and_then(|guild_id| {
+                state
+                    .config
+                    .guilds()
+                    .get(&guild_id)
+                    .and_then(|guild_config| guild_config.notification_override(msg.channel_id))
+            });
+            let notifications = state.conn.notification_settings.read().resolve(
+                cache,
+                msg.guild_id,
+                msg.channel_id,
+                local_override,
+            );
 
-                if !(mentioned || private) {
-                    tags.insert("notify_message".into());
-                }
-            }
+            let mentioned = cache.current_user().map_or(false, |user| {
+                let user_mentioned = msg.mentions.iter().any(|m| m.id == user.id);
+
+                let role_mentioned = state.config.highlight_roles()
+                    && !notifications.suppress_roles
+                    && !msg.mention_roles.is_empty()
+                    && msg
+                        .guild_id
+                        .and_then(|guild_id| cache.member(guild_id, user.id))
+                        .map_or(false, |member| {
+                            msg.mention_roles
+                                .iter()
+                                .any(|role| member.roles().contains(role))
+                        });
+
+                let everyone_mentioned = state.config.highlight_everyone()
+                    && !notifications.suppress_everyone
+                    && msg.mention_everyone;
+
+                user_mentioned || role_mentioned || everyone_mentioned
+            });
+
+            let notify = match notifications.level {
+                NotificationLevel::Nothing => "notify_none",
+                _ if mentioned => "notify_highlight",
+                _ if notifications.muted => "notify_none",
+                _ if private => "notify_private",
+                NotificationLevel::Mentions => "notify_low",
+                NotificationLevel::All => "notify_message",
+            };
+            tags.insert(notify.into());
         };
 
         match self {
@@ -230,19 +341,81 @@ impl WeechatMessage<Id<MessageMarker>, State> for WeecordMessage {
     fn id(&self, _: &mut State) -> Id<MessageMarker> {
         self.id()
     }
+
+    fn line_tag(&self, _: &mut State) -> String {
+        format!("discord_msg_{}", self.id())
+    }
 }
 
 pub struct State {
     conn: ConnectionInner,
     config: Config,
     unknown_members: Vec<Id<UserMarker>>,
+    references: ReferencedMessages,
+    /// Messages whose spoilers have been revealed with `/discord spoiler reveal`
+    revealed_spoilers: HashSet<Id<MessageMarker>>,
+    reveal_all_spoilers: bool,
+}
+
+impl State {
+    /// The mask to hide the spoilers of a message behind, `None` if they are revealed
+    fn spoiler_mask(&self, id: Id<MessageMarker>) -> Option<String> {
+        if self.reveal_all_spoilers || self.revealed_spoilers.contains(&id) {
+            None
+        } else {
+            Some(self.config.spoiler_char())
+        }
+    }
+}
+
+/// Original messages of replies which the api did not include, such as replies to replies
+#[derive(Default)]
+struct ReferencedMessages {
+    /// `None` if the original message has been deleted
+    resolved: HashMap<Id<MessageMarker>, Option<DiscordMessage>>,
+    /// References encountered while rendering that need to be fetched
+    unresolved: HashSet<(Id<ChannelMarker>, Id<MessageMarker>)>,
+    requested: HashSet<Id<MessageMarker>>,
+    /// The number of failed fetches of each reference, to back off retries
+    failures: HashMap<Id<MessageMarker>, u32>,
+}
+
+/// Re-renders requested by bursts of events, flushed together
+#[derive(Default)]
+struct PendingRedraws {
+    /// Messages to re-render in place
+    messages: Vec<Id<MessageMarker>>,
+    /// Whether the whole buffer must be redrawn, which also covers `messages`
+    full: bool,
+    ignore_users: Vec<Id<UserMarker>>,
+    /// Whether the renderer is in `DIRTY_RENDERERS`
+    dirty: bool,
+    /// Whether a delayed flush is waiting
+    scheduled: bool,
+    last_flush: Option<Instant>,
+}
+
+thread_local! {
+    /// Renderers with pending re-renders
+    static DIRTY_RENDERERS: RefCell<Vec<WeecordRenderer>> = RefCell::new(Vec::new());
+}
+
+/// Flush the pending re-renders of every buffer that has not been flushed within the redraw
+/// interval, called once the event queue drains
+pub fn flush_pending_redraws() {
+    let dirty = DIRTY_RENDERERS.with(|dirty| std::mem::take(&mut *dirty.borrow_mut()));
+    for renderer in dirty {
+        renderer.redraws.borrow_mut().dirty = false;
+        renderer.flush_redraws_if_due();
+    }
 }
 
+#[derive(Clone)]
 pub struct WeecordRenderer {
     inner: MessageRenderer<WeecordMessage, Id<MessageMarker>, State>,
-    #[cfg(feature = "images")]
     config: Config,
     conn: ConnectionInner,
+    redraws: Rc<RefCell<PendingRedraws>>,
 }
 
 impl WeecordRenderer {
@@ -259,11 +432,14 @@ impl WeecordRenderer {
                     conn: connection.clone(),
                     config: config.clone(),
                     unknown_members: Vec::new(),
+                    references: ReferencedMessages::default(),
+                    revealed_spoilers: HashSet::new(),
+                    reveal_all_spoilers: false,
                 },
             ),
-            #[cfg(feature = "images")]
             config: config.clone(),
             conn: connection.clone(),
+            redraws: Rc::new(RefCell::new(PendingRedraws::default())),
         }
     }
 
@@ -274,14 +450,54 @@ impl WeecordRenderer {
     pub fn set_last_read_id(&self, id: Id<MessageMarker>) {
         self.inner.set_last_read_id(id);
     }
+
+    /// Move the read marker to a message that was read elsewhere, such as another client
+    pub fn mark_read(&self, id: Id<MessageMarker>) {
+        if self
+            .inner
+            .last_read_id()
+            .map_or(false, |last_read| last_read >= id)
+        {
+            return;
+        }
+        self.inner.set_last_read_id(id);
+        let notification_removed = self.remove_ephemeral_notification();
+
+        let newest_id = self
+            .inner
+            .messages()
+            .borrow()
+            .iter()
+            .find(|msg| {
+                !matches!(
+                    msg,
+                    WeecordMessage::LocalEcho { .. } | WeecordMessage::Notification { .. }
+                )
+            })
+            .map(WeecordMessage::id);
+        // Everything has been read, so the marker can be moved to the end without redrawing
+        // unless a notification has to disappear
+        if newest_id.map_or(true, |newest_id| newest_id <= id) {
+            if notification_removed {
+                self.inner.redraw_buffer();
+            }
+            if let Ok(buffer) = self.buffer_handle().upgrade() {
+                buffer.mark_read();
+                buffer.clear_hotlist();
+            }
+        } else {
+            self.redraw_buffer(&[]);
+        }
+    }
+
     /// Clear the buffer and reprint all messages
     pub fn redraw_buffer(&self, ignore_users: &[Id<UserMarker>]) {
         self.inner.state().borrow_mut().unknown_members.clear();
 
         self.inner.redraw_buffer();
 
-        let state = self.inner.state();
         {
+            let state = self.inner.state();
             let mut state = state.borrow_mut();
             let unknown_members = &mut state.unknown_members;
             // TODO: Use drain_filter when it stabilizes
@@ -293,10 +509,119 @@ impl WeecordRenderer {
             }
         }
 
+        self.fetch_unknown_members();
+        self.fetch_referenced_messages();
+    }
+
+    /// Re-render a message after it changed, only updating its own lines when their number is
+    /// unchanged
+    pub fn refresh_message(&self, id: Id<MessageMarker>) {
+        self.refresh_messages(&[id]);
+    }
+
+    /// Re-render several changed messages, with at most one redraw of the whole buffer
+    pub fn refresh_messages(&self, ids: &[Id<MessageMarker>]) {
+        self.inner.state().borrow_mut().unknown_members.clear();
+
+        self.inner.refresh_messages(ids);
+
+        self.fetch_unknown_members();
+        self.fetch_referenced_messages();
+    }
+
+    /// Queue a message to be re-rendered with the next flush
+    pub fn schedule_refresh(&self, id: Id<MessageMarker>) {
+        self.schedule(|pending| {
+            if !pending.messages.contains(&id) {
+                pending.messages.push(id);
+            }
+        });
+    }
+
+    /// Queue a redraw of the whole buffer with the next flush
+    pub fn schedule_redraw(&self, ignore_users: &[Id<UserMarker>]) {
+        self.schedule(|pending| {
+            pending.full = true;
+            pending.ignore_users.extend_from_slice(ignore_users);
+        });
+    }
+
+    fn schedule(&self, f: impl FnOnce(&mut PendingRedraws)) {
+        let (newly_dirty, needs_flush) = {
+            let mut pending = self.redraws.borrow_mut();
+            f(&mut pending);
+            (
+                !std::mem::replace(&mut pending.dirty, true),
+                !std::mem::replace(&mut pending.scheduled, true),
+            )
+        };
+
+        if newly_dirty {
+            DIRTY_RENDERERS.with(|dirty| dirty.borrow_mut().push(self.clone()));
+        }
+
+        // Changes which don't come from the event queue, or arrive while it never drains, are
+        // still flushed after the interval
+        if needs_flush {
+            let renderer = self.clone();
+            let rt = self.conn.rt.clone();
+            let interval = self.redraw_interval();
+            Weechat::spawn(async move {
+                rt.spawn(tokio::time::sleep(interval))
+                    .await
+                    .expect("Task is never aborted");
+                renderer.redraws.borrow_mut().scheduled = false;
+                renderer.flush_redraws();
+            })
+            .detach();
+        }
+    }
+
+    fn redraw_interval(&self) -> Duration {
+        Duration::from_millis(self.config.redraw_interval() as u64)
+    }
+
+    fn flush_redraws_if_due(&self) {
+        let due = self.redraws.borrow().last_flush.map_or(true, |last_flush| {
+            last_flush.elapsed() >= self.redraw_interval()
+        });
+        if due {
+            self.flush_redraws();
+        }
+    }
+
+    fn flush_redraws(&self) {
+        // The buffer may have been closed while the flush was pending
+        if self.buffer_handle().upgrade().is_err() {
+            return;
+        }
+
+        let (messages, full, ignore_users) = {
+            let mut pending = self.redraws.borrow_mut();
+            if !pending.full && pending.messages.is_empty() {
+                return;
+            }
+            pending.last_flush = Some(Instant::now());
+            (
+                std::mem::take(&mut pending.messages),
+                std::mem::take(&mut pending.full),
+                std::mem::take(&mut pending.ignore_users),
+            )
+        };
+
+        if full {
+            self.redraw_buffer(&ignore_users);
+        } else {
+            self.refresh_messages(&messages);
+        }
+    }
+
+    /// Request the members found while rendering that are missing from the cache
+    fn fetch_unknown_members(&self) {
         if let Some(WeecordMessage::Text(first_msg)) = self.inner.messages().borrow().front() {
             if let Some(guild_id) = first_msg.guild_id {
                 self.fetch_guild_members(
-                    &state.borrow().unknown_members,
+                    &self.inner.state().borrow().unknown_members,
                     first_msg.channel_id,
                     guild_id,
                 );
@@ -308,12 +633,14 @@ impl WeecordRenderer {
         self.inner.state().borrow_mut().unknown_members.clear();
         self.clear_ephemeral_notifications();
 
-        let mut msgs = msgs.into_iter().peekable();
+        let msgs: Vec<_> = msgs.collect();
         let guild_id = msgs
-            .peek()
+            .first()
             .and_then(|msg| msg.guild_id.map(|g| (g, msg.channel_id)));
 
-        let msgs = msgs.map(|msg| {
+        self.resolve_loaded_references(&msgs);
+
+        let msgs = msgs.into_iter().map(|msg| {
             #[cfg(feature = "images")]
             self.load_images(&msg);
 
@@ -321,6 +648,7 @@ impl WeecordRenderer {
         });
 
         self.inner.add_bulk_msgs(msgs.into_iter());
+        self.prune_references();
 
         if let Some((guild_id, channel_id)) = guild_id {
             self.fetch_guild_members(
@@ -329,9 +657,18 @@ impl WeecordRenderer {
                 guild_id,
             );
         }
+
+        self.fetch_referenced_messages();
     }
 
     fn clear_ephemeral_notifications(&self) {
+        if self.remove_ephemeral_notification() {
+            self.inner.redraw_buffer();
+        }
+    }
+
+    /// Remove the ephemeral notification without redrawing, returns whether there was one
+    fn remove_ephemeral_notification(&self) -> bool {
         let notification = match self
             .inner
             .messages()
@@ -341,16 +678,16 @@ impl WeecordRenderer {
             .cloned()
         {
             Some(pos) => pos,
-            _ => return,
+            _ => return false,
         };
         self.inner.remove_msg(&notification.id());
-        self.inner.redraw_buffer();
+        true
     }
 
     #[cfg(feature = "images")]
     fn load_images(&self, msg: &DiscordMessage) {
         for candidate in find_image_candidates(msg) {
-            let renderer = self.inner.clone();
+            let renderer = self.clone();
             let rt = self.conn.rt.clone();
             let msg_id = msg.id;
             let max_height = self.config.image_max_height() as u32;
@@ -359,7 +696,7 @@ impl WeecordRenderer {
                     Ok(image) => {
                         let image =
                             term_image::resize_image(&image, (4, 8), (max_height as u16, u16::MAX));
-                        renderer.update_message(&msg_id, |msg| {
+                        renderer.inner.update_message(&msg_id, |msg| {
                             let loaded_image = LoadedImage {
                                 image,
                                 height: candidate.height,
@@ -376,7 +713,7 @@ impl WeecordRenderer {
                                 _ => {},
                             }
                         });
-                        renderer.redraw_buffer();
+                        renderer.schedule_refresh(msg_id);
                     },
                     Err(e) => {
                         tracing::error!("Failed to fetch image: {}", e);
@@ -420,7 +757,10 @@ impl WeecordRenderer {
 
         self.inner.state().borrow_mut().unknown_members.clear();
 
+        self.resolve_loaded_references(std::slice::from_ref(msg));
+
         self.inner.add_msg(WeecordMessage::new(msg.clone()));
+        self.prune_references();
 
         if let Some(guild_id) = msg.guild_id {
             self.fetch_guild_members(
@@ -429,6 +769,149 @@ impl WeecordRenderer {
                 guild_id,
             );
         }
+
+        self.fetch_referenced_messages();
+    }
+
+    /// Resolve the originals of replies from the new messages or those already in the buffer
+    fn resolve_loaded_references(&self, new_msgs: &[DiscordMessage]) {
+        let messages = self.inner.messages();
+        let messages = messages.borrow();
+        let loaded = messages.iter().filter_map(|msg| match msg {
+            WeecordMessage::Text(msg) => Some(msg.as_ref()),
+            #[cfg(feature = "images")]
+            WeecordMessage::Image { msg, .. } => Some(msg.as_ref()),
+            _ => None,
+        });
+        let candidates: Vec<_> = new_msgs.iter().chain(loaded).collect();
+
+        let state = self.inner.state();
+        let mut state = state.borrow_mut();
+        for msg in new_msgs {
+            if msg.kind != MessageType::Reply || msg.referenced_message.is_some() {
+                continue;
+            }
+            let ref_id = match msg.reference.as_ref().and_then(|r| r.message_id) {
+                Some(ref_id) => ref_id,
+                None => continue,
+            };
+            if state.references.resolved.contains_key(&ref_id) {
+                continue;
+            }
+            if let Some(original) = candidates.iter().find(|candidate| candidate.id == ref_id) {
+                state
+                    .references
+                    .resolved
+                    .insert(ref_id, Some((*original).clone()));
+            }
+        }
+    }
+
+    /// Forget the originals of replies which are no longer in the buffer
+    fn prune_references(&self) {
+        let referenced: HashSet<_> = self
+            .inner
+            .messages()
+            .borrow()
+            .iter()
+            .filter_map(WeecordMessage::reference_id)
+            .collect();
+
+        let state = self.inner.state();
+        let references = &mut state.borrow_mut().references;
+        references.resolved.retain(|id, _| referenced.contains(id));
+        references.requested.retain(|id| referenced.contains(id));
+        references.failures.retain(|id, _| referenced.contains(id));
+    }
+
+    /// Queue the replies to a message to be re-rendered
+    fn refresh_replies(&self, message_id: Id<MessageMarker>) {
+        let replies: Vec<_> = self
+            .messages()
+            .borrow()
+            .iter()
+            .filter(|msg| msg.reference_id() == Some(message_id))
+            .map(WeecordMessage::id)
+            .collect();
+        for reply in replies {
+            self.schedule_refresh(reply);
+        }
+    }
+
+    /// Fetch the originals of replies that could not be resolved locally and re-render the
+    /// replies once they arrive, failed fetches are retried with a backoff
+    fn fetch_referenced_messages(&self) {
+        let unresolved: Vec<_> = {
+            let state = self.inner.state();
+            let mut state = state.borrow_mut();
+            let references = &mut state.references;
+            let unresolved = std::mem::take(&mut references.unresolved);
+            unresolved
+                .into_iter()
+                .filter(|(_, message_id)| references.requested.insert(*message_id))
+                .collect()
+        };
+
+        for (channel_id, message_id) in unresolved {
+            let renderer = self.clone();
+            let rt = self.conn.rt.clone();
+            let http = self.conn.http.clone();
+            Weechat::spawn(async move {
+                let result: anyhow::Result<_> = rt
+                    .spawn(async move {
+                        match http.message(channel_id, message_id).exec().await {
+                            Ok(response) => Ok(Some(response.model().await?)),
+                            Err(e) => match e.kind() {
+                                HttpErrorType::Response { status, .. } if status.get() == 404 => {
+                                    Ok(None)
+                                },
+                                _ => Err(e.into()),
+                            },
+                        }
+                    })
+                    .await
+                    .expect("Task is never aborted");
+
+                match result {
+                    Ok(original) => {
+                        {
+                            let state = renderer.inner.state();
+                            let references = &mut state.borrow_mut().references;
+                            references.failures.remove(&message_id);
+                            references.resolved.insert(message_id, original);
+                        }
+                        renderer.refresh_replies(message_id);
+                    },
+                    Err(e) => {
+                        tracing::error!(
+                            channel.id = channel_id.get(),
+                            message.id = message_id.get(),
+                            "Failed to fetch referenced message: {}",
+                            e
+                        );
+                        let failures = {
+                            let state = renderer.inner.state();
+                            let references = &mut state.borrow_mut().references;
+                            references.requested.remove(&message_id);
+                            let failures = references.failures.entry(message_id).or_insert(0);
+                            *failures += 1;
+                            *failures
+                        };
+
+                        // Retry after 2 seconds, doubling up to about 4 minutes
+                        let delay = Duration::from_secs(1 << failures.min(8));
+                        rt.spawn(tokio::time::sleep(delay))
+                            .await
+                            .expect("Task is never aborted");
+                        if renderer.buffer_handle().upgrade().is_ok() {
+                            // Rendering the replies again requests the original again
+                            renderer.refresh_replies(message_id);
+                        }
+                    },
+                }
+            })
+            .detach();
+        }
     }
 
     pub fn update_message<F>(&self, id: Id<MessageMarker>, f: F)
@@ -448,6 +931,32 @@ impl WeecordRenderer {
         self.inner.get_nth_message(index)
     }
 
+    /// Reveal the spoilers of the nth most recent message, returns false if there is no such
+    /// message
+    pub fn reveal_spoilers(&self, index: usize) -> bool {
+        let id = match self.inner.get_nth_message(index) {
+            Some(WeecordMessage::Text(msg)) => msg.id,
+            #[cfg(feature = "images")]
+            Some(WeecordMessage::Image { msg, .. }) => msg.id,
+            _ => return false,
+        };
+        self.inner.state().borrow_mut().revealed_spoilers.insert(id);
+        self.refresh_message(id);
+        true
+    }
+
+    /// Toggle revealing every spoiler in the buffer, returns whether they are now revealed
+    pub fn toggle_reveal_all_spoilers(&self) -> bool {
+        let reveal_all = {
+            let state = self.inner.state();
+            let mut state = state.borrow_mut();
+            state.reveal_all_spoilers = !state.reveal_all_spoilers;
+            state.reveal_all_spoilers
+        };
+        self.redraw_buffer(&[]);
+        reveal_all
+    }
+
     pub fn nth_oldest_message(&self, index: usize) -> Option<WeecordMessage> {
         self.inner.nth_oldest_message(index)
     }
@@ -458,11 +967,33 @@ impl WeecordRenderer {
 
     pub fn remove_msg(&self, id: Id<MessageMarker>) {
         self.inner.remove_msg(&id);
+
+        let replies: Vec<_> = self
+            .messages()
+            .borrow()
+            .iter()
+            .filter(|msg| msg.reference_id() == Some(id))
+            .map(WeecordMessage::id)
+            .collect();
+
+        {
+            let state = self.inner.state();
+            let resolved = &mut state.borrow_mut().references.resolved;
+            if !replies.is_empty() || resolved.contains_key(&id) {
+                resolved.insert(id, None);
+            }
+        }
+        for reply in replies {
+            self.update_message(reply, |msg| msg.referenced_message = None);
+        }
+        // The lines of the message can only be removed by redrawing, which covers the replies
+        self.schedule_redraw(&[]);
     }
 
     pub fn apply_message_update(&self, update: MessageUpdate) {
-        self.update_message(update.id, |msg| msg.update(update));
-        self.redraw_buffer(&[]);
+        let id = update.id;
+        self.update_message(id, |msg| msg.update(update));
+        self.schedule_refresh(id);
     }
 
     fn fetch_guild_members(
@@ -511,20 +1042,33 @@ fn render_msg(
     config: &Config,
     msg: &DiscordMessage,
     include_at: bool,
+    spoiler_mask: Option<&str>,
     unknown_members: &mut Vec<Id<UserMarker>>,
+    references: Option<&mut ReferencedMessages>,
 ) -> (String, String) {
     use twilight_model::channel::message::MessageType::*;
+    let colors = config.colors();
+    let action = is_action(msg);
+    // Actions are rendered without the italics they are sent with
+    let content = if action {
+        &msg.content[1..msg.content.len() - 1]
+    } else {
+        msg.content.as_str()
+    };
     let mut msg_content = crate::utils::discord_to_weechat(
-        &msg.content,
+        content,
         cache,
         msg.guild_id,
         config.show_formatting_chars(),
         config.show_unknown_user_ids(),
+        config.emoji_style(),
+        &colors,
+        spoiler_mask,
         unknown_members,
     );
 
     if msg.edited_timestamp.is_some() {
-        msg_content.push_styled_str(Style::color("8"), " (edited)");
+        msg_content.append(colorize_string(" (edited)", &colors.edited));
     }
 
     for attachment in &msg.attachments {
@@ -534,47 +1078,130 @@ fn render_msg(
         msg_content.push_str(&attachment.proxy_url);
     }
 
-    msg_content.append(format_embeds(msg, !msg_content.is_empty()));
+    msg_content.append(format_embeds(msg, !msg_content.is_empty(), &colors));
 
-    msg_content.append(format_reactions(msg));
+    msg_content.append(format_reactions(msg, config.emoji_style(), &colors));
 
     let (prefix, author) = format_author_prefix(cache, config, msg, include_at);
 
     let prefix = prefix.build();
     let msg_content = msg_content.build();
     match msg.kind {
+        Regular if action => (
+            Weechat::prefix(weechat::Prefix::Action),
+            format!("{} {}", author.build(), msg_content),
+        ),
         Regular => (prefix, msg_content),
         ChatInputCommand => (prefix, msg_content),
-        Reply => match msg.referenced_message.as_ref() {
-            Some(ref_msg) => {
-                let mut ref_msg = ref_msg.clone();
-                // The original message returned by the api does not include a guild id, even if the
-                // parent message has one, so we set it so that render_msg can lookup members/channels
-                // correctly
-                ref_msg.guild_id = msg.reference.as_ref().and_then(|m| m.guild_id);
-                let mentions_user = msg.mentions.iter().any(|m| m.id == ref_msg.author.id);
-                let (ref_prefix, ref_msg_content) =
-                    render_msg(cache, config, &ref_msg, mentions_user, &mut Vec::new());
-
-                let ref_msg_content = fold_lines(ref_msg_content.lines(), "▎");
-                (
+        Reply => {
+            // Quoted messages are rendered without their own reply preview
+            let references = match references {
+                Some(references) => references,
+                None => return (prefix, msg_content),
+            };
+            let reference = msg.reference.as_ref();
+            let ref_msg = match (
+                msg.referenced_message.as_deref(),
+                reference.and_then(|r| r.message_id),
+            ) {
+                (Some(ref_msg), _) => Some(ref_msg),
+                (None, Some(ref_id)) => match references.resolved.get(&ref_id) {
+                    Some(ref_msg) => ref_msg.as_ref(),
+                    None => {
+                        let channel_id = reference
+                            .and_then(|r| r.channel_id)
+                            .unwrap_or(msg.channel_id);
+                        references.unresolved.insert((channel_id, ref_id));
+                        return (
+                            prefix,
+                            format!(
+                                "{}\n{}",
+                                format_reply_marker("<loading reply>"),
+                                msg_content
+                            ),
+                        );
+                    },
+                },
+                (None, None) => None,
+            };
+
+            match ref_msg {
+                Some(ref_msg) => {
+                    let mut ref_msg = ref_msg.clone();
+                    // The original message returned by the api does not include a guild id, even if the
+                    // parent message has one, so we set it so that render_msg can lookup members/channels
+                    // correctly
+                    ref_msg.guild_id = reference.and_then(|m| m.guild_id);
+                    let mentions_user = msg.mentions.iter().any(|m| m.id == ref_msg.author.id);
+                    (
+                        prefix,
+                        format!(
+                            "{}\n{}",
+                            format_reply_preview(
+                                cache,
+                                config,
+                                &ref_msg,
+                                mentions_user,
+                                spoiler_mask
+                            ),
+                            msg_content
+                        ),
+                    )
+                },
+                None => (
                     prefix,
                     format!(
-                        "{}:\n{}\n{}",
-                        ref_prefix,
-                        ref_msg_content.build(),
+                        "{}\n{}",
+                        format_reply_marker("<original message deleted>"),
                         msg_content
                     ),
-                )
-            },
-            // TODO: Nested replies contain only ids, so cache lookup is needed
-            None => (prefix, format!("<nested reply>\n{}", msg_content)),
+                ),
+            }
         },
         _ => format_event_message(msg, &author.build()),
     }
 }
 
-fn format_embeds(msg: &DiscordMessage, leading_newline: bool) -> StyledString {
+/// Whether a message is a `/me` style action, sent as an entirely italic line
+fn is_action(msg: &DiscordMessage) -> bool {
+    msg.kind == MessageType::Regular && crate::utils::is_action(&msg.content)
+}
+
+/// Render the quoted author and content shown above a reply
+fn format_reply_preview(
+    cache: &InMemoryCache,
+    config: &Config,
+    ref_msg: &DiscordMessage,
+    mentions_user: bool,
+    spoiler_mask: Option<&str>,
+) -> String {
+    let (ref_prefix, ref_msg_content) = render_msg(
+        cache,
+        config,
+        ref_msg,
+        mentions_user,
+        spoiler_mask,
+        &mut Vec::new(),
+        None,
+    );
+
+    let ref_msg_content = fold_lines(ref_msg_content.lines(), "▎");
+    format!("{}:\n{}", ref_prefix, ref_msg_content.build())
+}
+
+/// Render the placeholder shown above a reply whose original message is unavailable
+fn format_reply_marker(marker: &str) -> String {
+    let mut out = StyledString::new();
+    out.push_styled_str(Style::color("8"), &format!("▎{}", marker));
+    out.build()
+}
+
+fn format_embeds(
+    msg: &DiscordMessage,
+    leading_newline: bool,
+    colors: &ColorConfig,
+) -> StyledString {
+    let bar = colorize_string("▎", &colors.embed_bar);
     let mut out = StyledString::new();
     for embed in &msg.embeds {
         if leading_newline {
@@ -582,7 +1209,7 @@ fn format_embeds(msg: &DiscordMessage, leading_newline: bool) -> StyledString {
         }
         if let Some(ref provider) = embed.provider {
             if let Some(name) = &provider.name {
-                out.push_str("▎");
+                out.append(bar.clone());
                 out.push_str(name);
                 if let Some(url) = &provider.url {
                     out.push_str(&format!(" ({})", url));
@@ -591,7 +1218,7 @@ fn format_embeds(msg: &DiscordMessage, leading_newline: bool) -> StyledString {
             }
         }
         if let Some(ref author) = embed.author {
-            out.push_str("▎");
+            out.append(bar.clone());
             out.push_style(Style::color("bold"));
             // TODO: Should we do something else here if None?
             out.push_str(&author.name.clone());
@@ -602,23 +1229,23 @@ fn format_embeds(msg: &DiscordMessage, leading_newline: bool) -> StyledString {
             out.push_str("\n");
         }
         if let Some(ref title) = embed.title {
-            out.append(fold_lines(title.lines(), "▎"));
+            out.append(fold_lines(title.lines(), bar.clone()));
 
             out.push_str("\n");
         }
         if let Some(ref description) = embed.description {
-            out.append(fold_lines(description.lines(), "▎"));
+            out.append(fold_lines(description.lines(), bar.clone()));
             out.push_str("\n");
         }
         for field in &embed.fields {
-            out.push_str("▎");
+            out.append(bar.clone());
             out.push_str(&field.name);
             out.push_str(": ");
             out.push_str(&field.value.lines().collect::<Vec<_>>().join(":"));
             out.push_str("\n");
         }
         if let Some(ref footer) = embed.footer {
-            out.append(fold_lines(footer.text.lines(), "▎"));
+            out.append(fold_lines(footer.text.lines(), bar.clone()));
             out.push_str("\n");
         }
     }
@@ -626,29 +1253,30 @@ fn format_embeds(msg: &DiscordMessage, leading_newline: bool) -> StyledString {
     out
 }
 
-fn format_reactions(msg: &DiscordMessage) -> StyledString {
+fn format_reactions(
+    msg: &DiscordMessage,
+    emoji_style: EmojiStyle,
+    colors: &ColorConfig,
+) -> StyledString {
     let mut out = StyledString::new();
-    if !msg.reactions.is_empty() {
+    for reaction in &msg.reactions {
+        let emoji = match &reaction.emoji {
+            ReactionType::Custom { name, .. } => match name {
+                Some(name) => format!(":{}:", name),
+                None => continue,
+            },
+            ReactionType::Unicode { name } => style_emojis(name, emoji_style).into_owned(),
+        };
+        let color = if reaction.me {
+            &colors.own_reactions
+        } else {
+            &colors.reactions
+        };
         out.push_str(" ");
-        out.push_style(Style::color("8"));
-    }
-
-    out.push_str(
-        &msg.reactions
-            .iter()
-            .flat_map(|reaction| {
-                match &reaction.emoji {
-                    ReactionType::Custom { name, .. } => name.clone().map(|n| format!(":{}:", n)),
-                    ReactionType::Unicode { name } => Some(name.clone()),
-                }
-                .map(|e| format!("[{} {}]", e, reaction.count))
-            })
-            .collect::<Vec<_>>()
-            .join(" "),
-    );
-
-    if !msg.reactions.is_empty() {
-        out.push_style(Style::color("-8"));
+        out.append(colorize_string(
+            &format!("[{} {}]", emoji, reaction.count),
+            color,
+        ));
     }
 
     out
@@ -790,7 +1418,14 @@ fn format_event_message(msg: &DiscordMessage, author: &str) -> (String, String)
         ),
         ThreadStarterMessage => (
             weechat::Prefix::Network,
-            "[Thread starter - Threads are not implemented]".to_owned(),
+            match msg.referenced_message.as_ref() {
+                Some(starter) => format!(
+                    "Thread started from {}: {}",
+                    bold(&starter.author.name),
+                    starter.content
+                ),
+                None => "Thread started from a deleted message".to_owned(),
+            },
         ),
         ContextMenuCommand => (
             weechat::Prefix::Network,